@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use flate2::read::GzDecoder;
+use path_encoding;
+use error::Error;
+use error::Result;
+
+/// Separates the archive from the member in entry paths, `corpus.tar#spk1/0001.wav`.
+pub const MEMBER_SEPARATOR: char = '#';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// Whether the dictionary is a `.tar`, `.tar.gz`, `.tgz` or `.zip` file rather than a directory.
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && archive_kind(path).is_some()
+}
+
+/// Path of an archive member as the output records it, `member` being escaped like `for_each_member` passes it.
+pub fn address(archive: &Path, member: &str) -> PathBuf {
+    let mut address = archive.as_os_str().to_os_string();
+    address.push(MEMBER_SEPARATOR.to_string());
+    address.push(path_encoding::unescape_bytes(member));
+    PathBuf::from(address)
+}
+
+/// Archive and escaped member of an address, `None` for plain paths.
+pub fn split_address(path: &Path) -> Option<(PathBuf, String)> {
+    // split in the escaped form, so archives with names that are not UTF-8 are found as well
+    let escaped = path_encoding::escape_bytes(path.as_os_str());
+    escaped.match_indices(MEMBER_SEPARATOR)
+        .map(|(idx, _)| (PathBuf::from(path_encoding::unescape_bytes(&escaped[..idx])), &escaped[idx + 1..]))
+        .find(|(archive, _)| archive_kind(archive).is_some())
+        .map(|(archive, member)| (archive, String::from(member)))
+}
+
+/// Member names with `/` separators and without a leading `./`, escaped by `path_encoding::escape_raw`.
+/// Having no backslashes left, names that are valid UTF-8 stay as they are.
+fn normalize_member(name: &[u8]) -> String {
+    let name: Vec<u8> = name.iter().map(|v| if *v == b'\\' { b'/' } else { *v }).collect();
+    let mut name = &name[..];
+    while let Some(v) = name.strip_prefix(b"./") {
+        name = v;
+    }
+    path_encoding::escape_raw(name)
+}
+
+fn for_each_tar_member<R, F>(path: &Path, reader: R, f: &mut F) -> Result<()>
+    where R: Read, F: FnMut(&str, &mut dyn Read) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(|e| Error::io(path, e))? {
+        let mut entry = entry.map_err(|e| Error::io(path, e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = normalize_member(&entry.path_bytes());
+        f(&name, &mut entry)?;
+    }
+    Ok(())
+}
+
+/// Streams every regular member of an archive through `f` in archive order, reading it at most once.
+/// Members `f` does not read are skipped without being decompressed into memory. Names that are not UTF-8
+/// reach `f` escaped, see `normalize_member`.
+pub fn for_each_member<F>(path: &Path, mut f: F) -> Result<()> where F: FnMut(&str, &mut dyn Read) -> Result<()> {
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    match archive_kind(path) {
+        Some(ArchiveKind::Tar) => for_each_tar_member(path, file, &mut f),
+        Some(ArchiveKind::TarGz) => for_each_tar_member(path, GzDecoder::new(file), &mut f),
+        Some(ArchiveKind::Zip) => {
+            let mut archive = zip::ZipArchive::new(file)
+                .map_err(|e| Error::Archive { path: path.to_path_buf(), message: e.to_string() })?;
+            for idx in 0..archive.len() {
+                let mut member = archive.by_index(idx)
+                    .map_err(|e| Error::Archive { path: path.to_path_buf(), message: e.to_string() })?;
+                if !member.is_file() {
+                    continue;
+                }
+                let name = normalize_member(member.name().as_bytes());
+                f(&name, &mut member)?;
+            }
+            Ok(())
+        },
+        None => Err(Error::Archive { path: path.to_path_buf(), message: String::from("unknown archive type") })
+    }
+}
+
+/// Reads a whole member, for `for_each_member` callbacks.
+pub fn read_member(path: &Path, member: &str, reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|e| Error::io(address(path, member), e))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_member_names() {
+        assert_eq!(normalize_member(b"./spk1\\a.wav"), "spk1/a.wav");
+        assert_eq!(normalize_member("šaltinis/ą.wav".as_bytes()), "šaltinis/ą.wav");
+        assert_eq!(normalize_member(b"spk\xe0/\xf0altinis.wav"), "spk\\xe0/\\xf0altinis.wav");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_member_bytes_in_addresses() {
+        use std::os::unix::ffi::OsStrExt;
+        let member = normalize_member(b"spk\xe0/a.wav");
+        let address = address(Path::new("/corpus.tar"), &member);
+        assert_eq!(address.as_os_str().as_bytes(), b"/corpus.tar#spk\xe0/a.wav");
+        assert_eq!(split_address(&address), Some((PathBuf::from("/corpus.tar"), member)));
+        assert_eq!(split_address(Path::new("/corpus/a#b.wav")), None);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use wav::WavHeader;
+use wav::FORMAT_PCM;
+use wav::FORMAT_IEEE_FLOAT;
+use error::Error;
+use error::Result;
+
+/// Level reported for digital silence instead of minus infinity.
+const FLOOR_DBFS: f64 = -120.0;
+/// Samples at or above this magnitude count as clipped.
+const CLIPPING_LEVEL: f64 = 0.999;
+/// Length of the windows silence is measured in.
+const WINDOW_SECONDS: f64 = 0.01;
+
+/// Parameters of the sample analysis.
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    /// Windows with an RMS level below this many dBFS are silent.
+    pub silence_threshold_db: f64
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> AnalysisOptions {
+        AnalysisOptions { silence_threshold_db: -40.0 }
+    }
+}
+
+/// Signal metrics of a recording, levels relative to full scale.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioQuality {
+    /// Share of samples at full scale, in percent.
+    pub clipping_percent: f64,
+    /// Mean sample value, full scale being 1.
+    pub dc_offset: f64,
+    pub peak_dbfs: f64,
+    pub rms_dbfs: f64,
+    pub leading_silence_seconds: f64,
+    pub trailing_silence_seconds: f64,
+    /// Share of silent windows, 0 to 1.
+    pub silence_ratio: f64
+}
+
+fn dbfs(level: f64) -> f64 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(FLOOR_DBFS)
+    } else {
+        FLOOR_DBFS
+    }
+}
+
+/// Decodes the sample data to interleaved values in -1..1. Only 16-bit PCM and 32-bit float are supported.
+fn decode_samples(bytes: &[u8], header: &WavHeader) -> Option<Vec<f64>> {
+    match (header.format, header.bits_per_sample) {
+        (FORMAT_PCM, 16) => Some(bytes.chunks_exact(2)
+            .map(|v| f64::from(i16::from_le_bytes([v[0], v[1]])) / 32768.0)
+            .collect()),
+        (FORMAT_IEEE_FLOAT, 32) => Some(bytes.chunks_exact(4)
+            .map(|v| f64::from(f32::from_le_bytes([v[0], v[1], v[2], v[3]])))
+            .collect()),
+        _ => None
+    }
+}
+
+impl AudioQuality {
+    /// Reads and measures the samples of a file, `None` for sample formats other than 16-bit PCM and 32-bit float.
+    pub fn analyze(path: &Path, header: &WavHeader, options: &AnalysisOptions) -> Result<Option<AudioQuality>> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        file.seek(SeekFrom::Start(header.data_offset)).map_err(|e| Error::io(path, e))?;
+        let mut bytes = Vec::with_capacity(header.data_size as usize);
+        file.take(header.data_size).read_to_end(&mut bytes).map_err(|e| Error::io(path, e))?;
+
+        Ok(decode_samples(&bytes, header).map(|samples| AudioQuality::measure(&samples, header, options)))
+    }
+
+    /// `analyze` on a whole WAV file already read into memory.
+    pub fn analyze_bytes(bytes: &[u8], header: &WavHeader, options: &AnalysisOptions) -> Option<AudioQuality> {
+        let start = (header.data_offset as usize).min(bytes.len());
+        let end = (header.data_offset + header.data_size).min(bytes.len() as u64) as usize;
+        decode_samples(&bytes[start..end], header).map(|samples| AudioQuality::measure(&samples, header, options))
+    }
+
+    /// Computes the metrics of interleaved samples. Silence is judged on windows spanning all channels.
+    pub fn measure(samples: &[f64], header: &WavHeader, options: &AnalysisOptions) -> AudioQuality {
+        if samples.is_empty() {
+            return AudioQuality {
+                clipping_percent: 0.0,
+                dc_offset: 0.0,
+                peak_dbfs: FLOOR_DBFS,
+                rms_dbfs: FLOOR_DBFS,
+                leading_silence_seconds: 0.0,
+                trailing_silence_seconds: 0.0,
+                silence_ratio: 1.0
+            };
+        }
+
+        let count = samples.len() as f64;
+        let clipped = samples.iter().filter(|v| v.abs() >= CLIPPING_LEVEL).count();
+        let peak = samples.iter().fold(0.0f64, |acc, v| acc.max(v.abs()));
+        let sum: f64 = samples.iter().sum();
+        let power: f64 = samples.iter().map(|v| v * v).sum();
+
+        let window_frames = ((f64::from(header.sample_rate) * WINDOW_SECONDS) as usize).max(1);
+        let window = window_frames * usize::from(header.channels);
+        let silent: Vec<bool> = samples.chunks(window)
+            .map(|w| dbfs((w.iter().map(|v| v * v).sum::<f64>() / w.len() as f64).sqrt()) < options.silence_threshold_db)
+            .collect();
+        let leading = silent.iter().take_while(|v| **v).count();
+        let trailing = if leading == silent.len() { 0 } else { silent.iter().rev().take_while(|v| **v).count() };
+        let window_seconds = window_frames as f64 / f64::from(header.sample_rate);
+
+        AudioQuality {
+            clipping_percent: 100.0 * clipped as f64 / count,
+            dc_offset: sum / count,
+            peak_dbfs: dbfs(peak),
+            rms_dbfs: dbfs((power / count).sqrt()),
+            leading_silence_seconds: leading as f64 * window_seconds,
+            trailing_silence_seconds: trailing as f64 * window_seconds,
+            silence_ratio: silent.iter().filter(|v| **v).count() as f64 / silent.len() as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wav::FORMAT_ALAW;
+
+    fn header(format: u16, channels: u16, bits: u16, data_size: u64) -> WavHeader {
+        WavHeader {
+            format,
+            channels,
+            sample_rate: 1000,
+            block_align: channels * bits / 8,
+            bits_per_sample: bits,
+            data_offset: 0,
+            data_size
+        }
+    }
+
+    fn measure(samples: &[f64], channels: u16) -> AudioQuality {
+        AudioQuality::measure(samples, &header(FORMAT_PCM, channels, 16, samples.len() as u64 * 2), &AnalysisOptions::default())
+    }
+
+    #[test]
+    fn decodes_pcm16() {
+        let bytes: Vec<u8> = [0i16, 16384, -32768, 32767].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let samples = decode_samples(&bytes, &header(FORMAT_PCM, 1, 16, 8)).unwrap();
+        assert_eq!(samples, vec![0.0, 0.5, -1.0, 32767.0 / 32768.0]);
+    }
+
+    #[test]
+    fn decodes_float32() {
+        let bytes: Vec<u8> = [0.0f32, 0.25, -1.0, 1.0].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let samples = decode_samples(&bytes, &header(FORMAT_IEEE_FLOAT, 1, 32, 16)).unwrap();
+        assert_eq!(samples, vec![0.0, 0.25, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn leaves_other_formats_undecoded() {
+        assert_eq!(decode_samples(&[0u8; 6], &header(FORMAT_PCM, 1, 24, 6)), None);
+        assert_eq!(decode_samples(&[0u8; 4], &header(FORMAT_ALAW, 1, 8, 4)), None);
+    }
+
+    #[test]
+    fn analyzes_only_the_data_chunk() {
+        let mut bytes = vec![0xFFu8; 4];
+        bytes.extend_from_slice(&16384i16.to_le_bytes());
+        bytes.extend_from_slice(&16384i16.to_le_bytes());
+        bytes.extend_from_slice(&[0xFFu8; 2]);
+        let header = WavHeader { data_offset: 4, ..header(FORMAT_PCM, 1, 16, 4) };
+        let quality = AudioQuality::analyze_bytes(&bytes, &header, &AnalysisOptions::default()).unwrap();
+        assert_eq!(quality.dc_offset, 0.5);
+        assert_eq!(quality.clipping_percent, 0.0);
+    }
+
+    #[test]
+    fn measures_clipping_and_dc_offset() {
+        let quality = measure(&[1.0, -1.0, 0.5, 0.0], 1);
+        assert_eq!(quality.clipping_percent, 50.0);
+        assert_eq!(quality.dc_offset, 0.125);
+        assert_eq!(quality.peak_dbfs, 0.0);
+
+        let quality = measure(&[0.25; 100], 1);
+        assert_eq!(quality.clipping_percent, 0.0);
+        assert_eq!(quality.dc_offset, 0.25);
+        assert!((quality.rms_dbfs - 20.0 * 0.25f64.log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn measures_silence() {
+        // 10 ms windows of 10 samples: 10 silent, 20 loud and 10 silent ones
+        let mut samples = vec![0.0; 100];
+        samples.extend(vec![0.5; 200]);
+        samples.extend(vec![0.0; 100]);
+        let quality = measure(&samples, 1);
+        assert_eq!(quality.silence_ratio, 0.5);
+        assert!((quality.leading_silence_seconds - 0.1).abs() < 1e-9);
+        assert!((quality.trailing_silence_seconds - 0.1).abs() < 1e-9);
+
+        // stereo windows span both channels, so the same samples make half as many windows
+        let quality = measure(&samples, 2);
+        assert_eq!(quality.silence_ratio, 0.5);
+        assert!((quality.leading_silence_seconds - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn counts_all_silence_as_leading() {
+        let quality = measure(&[0.0; 100], 1);
+        assert_eq!(quality.silence_ratio, 1.0);
+        assert!((quality.leading_silence_seconds - 0.1).abs() < 1e-9);
+        assert_eq!(quality.trailing_silence_seconds, 0.0);
+        assert_eq!(quality.rms_dbfs, FLOOR_DBFS);
+    }
+
+    #[test]
+    fn measures_empty_recordings() {
+        let quality = measure(&[], 1);
+        assert_eq!((quality.silence_ratio, quality.peak_dbfs, quality.clipping_percent), (1.0, FLOOR_DBFS, 0.0));
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::rename;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use sha2::Digest;
+use sha2::Sha256;
+use dict_entry::DictEntry;
+use phonemes::Phoneme;
+use phoneme_resolvers::PhonemeResolver;
+use pipeline::TranscriptOptions;
+use training_entry::EntryOptions;
+use training_entry::TrainingEntry;
+use training_entry::TranscriptRules;
+use training_entry::WordRecord;
+use error::Error;
+use error::Result;
+
+/// Bumped whenever the layout of the cache file changes.
+const CACHE_VERSION: u32 = 2;
+
+/// Hex SHA-256 of some bytes.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|v| format!("{:02x}", v)).collect()
+}
+
+/// Hex SHA-256 of a file, read in chunks so model files need not fit in memory.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut reader = BufReader::new(File::open(path).map_err(|e| Error::io(path, e))?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer).map_err(|e| Error::io(path, e))?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(hasher.finalize().iter().map(|v| format!("{:02x}", v)).collect())
+}
+
+/// Fingerprint of everything but the resolvers that shapes a converted transcript: the rule files, the variant
+/// mode and the version of the walker, whose built-in rules may change between releases.
+pub fn rules_fingerprint(options: &TranscriptOptions, entry: &EntryOptions) -> Result<String> {
+    let hash_rules = |path: &Option<PathBuf>| match path {
+        Some(v) => hash_file(v),
+        None => Ok(String::from("built-in"))
+    };
+    let description = format!("{} spelling:{} markers:{} variants:{:?}",
+        env!("CARGO_PKG_VERSION"), hash_rules(&options.spelling_rules)?, hash_rules(&options.markers)?, entry.variants);
+    Ok(content_hash(description.as_bytes()))
+}
+
+/// `Phoneme` field by field; its own serialization is the output notation, which loses the marker ordinals.
+#[derive(Serialize, Deserialize)]
+struct CachedPhoneme {
+    symbol: String,
+    ordinal: i8,
+    accented: bool,
+    valid: bool
+}
+
+fn store(phonemes: &[Phoneme]) -> Vec<CachedPhoneme> {
+    phonemes.iter()
+        .map(|v| CachedPhoneme { symbol: v.symbol.clone(), ordinal: v.ordinal, accented: v.accented, valid: v.valid })
+        .collect()
+}
+
+fn restore(phonemes: &[CachedPhoneme]) -> Vec<Phoneme> {
+    phonemes.iter()
+        .map(|v| Phoneme { symbol: v.symbol.clone(), ordinal: v.ordinal, accented: v.accented, valid: v.valid })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedWord {
+    word: String,
+    resolver: String,
+    variants: Vec<Vec<CachedPhoneme>>,
+    /// Names and word fingerprints of the resolvers asked for the word, up to the one that answered. Resolvers
+    /// further down the chain were never asked, so changing them leaves the word valid.
+    consulted: Vec<(String, String)>
+}
+
+/// A converted transcript along with the resolver answers it depends on.
+#[derive(Serialize, Deserialize)]
+struct CachedEntry {
+    transcript: String,
+    phonemes: Vec<CachedPhoneme>,
+    phoneme_lattice: Option<String>,
+    words: Vec<CachedWord>,
+    word_count: usize,
+    /// Markers detached from words while tokenizing, warned about again on every hit.
+    glued: Vec<String>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    rules: String,
+    /// Keyed by the content hash of the transcript.
+    entries: HashMap<String, CachedEntry>
+}
+
+/// Word records of a cached entry, `None` if one of the words lost its pronunciations.
+fn restore_words(words: &[CachedWord]) -> Option<Vec<WordRecord>> {
+    words.iter()
+        .map(|w| {
+            let variants: Vec<Vec<Phoneme>> = w.variants.iter().map(|v| restore(v)).collect();
+            variants.first().cloned().map(|phonemes| WordRecord {
+                word: w.word.clone(),
+                phonemes,
+                resolver: w.resolver.clone(),
+                variants
+            })
+        })
+        .collect()
+}
+
+/// On-disk cache of converted transcripts, so re-runs only send added or changed transcripts through the resolvers.
+pub struct EntryCache {
+    path: PathBuf,
+    rules: String,
+    /// Resolver names and fingerprints, in chain order.
+    chain: Vec<(String, String)>,
+    entries: HashMap<String, CachedEntry>,
+    /// Keys of the entries met in this run, the only ones saved.
+    used: HashSet<String>,
+    pub hits: usize,
+    pub misses: usize
+}
+
+impl EntryCache {
+    /// Loads the cache at `path`, which need not exist yet. Entries made with other rules, or with words whose
+    /// answers may have changed since, are dropped.
+    pub fn load(path: &Path, rules: String, resolvers: &[Box<dyn PhonemeResolver>]) -> Result<EntryCache> {
+        let mut chain: Vec<(String, String)> = Vec::new();
+        for resolver in resolvers.iter() {
+            chain.push((resolver.name(), resolver.fingerprint()?));
+        }
+
+        let mut cache = EntryCache { path: path.to_path_buf(), rules, chain, entries: HashMap::new(), used: HashSet::new(), hits: 0, misses: 0 };
+        if path.exists() {
+            let file = File::open(path).map_err(|e| Error::io(path, e))?;
+            match serde_json::from_reader::<_, CacheFile>(BufReader::new(file)) {
+                Ok(ref file) if file.version != CACHE_VERSION || file.rules != cache.rules =>
+                    info!("Cache {:?} was made with other rules or another version, starting over.", path),
+                Ok(file) => {
+                    let total = file.entries.len();
+                    let entries: HashMap<String, CachedEntry> = file.entries.into_iter()
+                        .filter(|(_, v)| v.words.iter().all(|w| cache.is_current(w, resolvers)))
+                        .collect();
+                    info!("Loaded {} cached entries from {:?}.", entries.len(), path);
+                    if entries.len() < total {
+                        info!("Dropped {} cached entries depending on changed resolvers.", total - entries.len());
+                    }
+                    cache.entries = entries;
+                },
+                Err(e) => warn!("Ignoring unreadable cache {:?}: {}", path, e)
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Names and word fingerprints of the resolvers asked for `word` before `resolver` answered it,
+    /// `None` if that resolver is no longer in the chain.
+    fn consulted(&self, word: &str, resolver: &str, resolvers: &[Box<dyn PhonemeResolver>]) -> Option<Vec<(String, String)>> {
+        let last = self.chain.iter().position(|v| v.0 == resolver)?;
+        Some(self.chain.iter().zip(resolvers.iter())
+            .take(last + 1)
+            .map(|((name, fingerprint), r)| (name.clone(), r.word_fingerprint(word).unwrap_or_else(|| fingerprint.clone())))
+            .collect())
+    }
+
+    /// Whether the resolvers would still give a cached word the same answer.
+    fn is_current(&self, word: &CachedWord, resolvers: &[Box<dyn PhonemeResolver>]) -> bool {
+        self.consulted(&word.word, &word.resolver, resolvers).as_ref() == Some(&word.consulted)
+    }
+
+    /// `TrainingEntry::construct`, reusing the cached conversion of an identical transcript.
+    pub fn construct(&mut self, de: DictEntry, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], options: &EntryOptions) -> Result<TrainingEntry> {
+        let key = content_hash(de.transcript.as_bytes());
+        self.used.insert(key.clone());
+        if let Some(cached) = self.entries.get(&key) {
+            if let Some(words) = restore_words(&cached.words) {
+                self.hits += 1;
+                for chunk in cached.glued.iter() {
+                    warn!("Detached marker glued to a word in \"{}\" ({:?})", chunk, de.transcript_path);
+                }
+                return Ok(TrainingEntry {
+                    id: de.id,
+                    transcript: cached.transcript.clone(),
+                    phonemes: restore(&cached.phonemes),
+                    phoneme_lattice: cached.phoneme_lattice.clone(),
+                    words: if options.provenance { Some(words) } else { None },
+                    audio_path: options.path_encoding.encode(&de.audio_path),
+                    audio: de.audio,
+                    quality: de.quality,
+                    word_count: cached.word_count
+                });
+            }
+        }
+
+        self.misses += 1;
+        let glued = TrainingEntry::tokenize(de.transcript.clone(), rules).glued;
+        // the word records tell which resolvers the entry depends on
+        let mut entry = TrainingEntry::construct(de, rules, resolvers, &EntryOptions { provenance: true, ..options.clone() })?;
+        let words = entry.words.take().unwrap_or_default();
+        let cached_words: Option<Vec<CachedWord>> = words.iter()
+            .map(|w| self.consulted(&w.word, &w.resolver, resolvers).map(|consulted| CachedWord {
+                word: w.word.clone(),
+                resolver: w.resolver.clone(),
+                variants: w.variants.iter().map(|v| store(v)).collect(),
+                consulted
+            }))
+            .collect();
+        if let Some(cached_words) = cached_words {
+            self.entries.insert(key, CachedEntry {
+                transcript: entry.transcript.clone(),
+                phonemes: store(&entry.phonemes),
+                phoneme_lattice: entry.phoneme_lattice.clone(),
+                words: cached_words,
+                word_count: entry.word_count,
+                glued
+            });
+        }
+        if options.provenance {
+            entry.words = Some(words);
+        }
+        Ok(entry)
+    }
+
+    /// Writes the cache next to its final location first, so an interrupted write leaves the old one intact.
+    pub fn save(&mut self) -> Result<()> {
+        // transcripts no longer in the dictionary would otherwise pile up
+        let used = &self.used;
+        self.entries.retain(|k, _| used.contains(k));
+        let cache = CacheFile {
+            version: CACHE_VERSION,
+            rules: self.rules.clone(),
+            entries: std::mem::take(&mut self.entries)
+        };
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp = self.path.with_file_name(temp_name);
+        {
+            let file = File::create(&temp).map_err(|e| Error::io(&temp, e))?;
+            let mut output = BufWriter::new(file);
+            serde_json::to_writer(&mut output, &cache)
+                .map_err(|e| Error::Serialization { path: temp.clone(), source: e })?;
+            output.flush().map_err(|e| Error::io(&temp, e))?;
+        }
+        rename(&temp, &self.path).map_err(|e| Error::io(&self.path, e))?;
+        self.entries = cache.entries;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use std::fs::remove_dir_all;
+    use std::fs::write;
+    use markers::MarkerTable;
+    use phoneme_resolvers::DeadEndPhonemeResolver;
+    use phoneme_resolvers::DictionaryPhonemeResolver;
+    use phoneme_resolvers::MarkerPhonemeResolver;
+
+    /// A fresh directory with a lexicon, and the path of a cache in it.
+    fn setup(name: &str, lexicon: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("dict_walker_cache_{}_{}", std::process::id(), name));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        write(dir.join("lexicon.txt"), lexicon).unwrap();
+        let cache = dir.join("out.cache");
+        (dir, cache)
+    }
+
+    fn resolvers(dir: &Path) -> Vec<Box<dyn PhonemeResolver>> {
+        vec![
+            Box::new(MarkerPhonemeResolver::new(MarkerTable::default())),
+            Box::new(DictionaryPhonemeResolver::load(&dir.join("lexicon.txt"), &MarkerTable::default()).unwrap()),
+            Box::new(DeadEndPhonemeResolver::new())
+        ]
+    }
+
+    fn entry(transcript: &str) -> DictEntry {
+        DictEntry { transcript: String::from(transcript), ..DictEntry::new_empty() }
+    }
+
+    /// Loads the cache and converts the transcripts through it, returning the cache before it is saved.
+    fn run(dir: &Path, cache: &Path, transcripts: &[&str]) -> EntryCache {
+        let resolvers = resolvers(dir);
+        let mut cache = EntryCache::load(cache, String::from("rules"), &resolvers).unwrap();
+        for transcript in transcripts.iter() {
+            cache.construct(entry(transcript), &TranscriptRules::default(), &resolvers, &EntryOptions::default()).unwrap();
+        }
+        cache
+    }
+
+    #[test]
+    fn reuses_entries() {
+        let (dir, path) = setup("reuse", "labas L A B A S\nrytas R I_ T A S\n");
+        let mut cache = run(&dir, &path, &["labas", "rytas"]);
+        assert_eq!((cache.hits, cache.misses), (0, 2));
+        cache.save().unwrap();
+        let cache = run(&dir, &path, &["labas", "rytas"]);
+        assert_eq!((cache.hits, cache.misses), (2, 0));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drops_only_entries_of_changed_words() {
+        let (dir, path) = setup("edit", "labas L A B A S\nrytas R I_ T A S\n");
+        run(&dir, &path, &["labas", "rytas", "diena"]).save().unwrap();
+        write(dir.join("lexicon.txt"), "labas L A B A S\nrytas R I T A S\ngera G E R A\n").unwrap();
+        let cache = run(&dir, &path, &["labas", "rytas", "diena"]);
+        // "diena" went to the dead end, which came after the dictionary that still does not know it
+        assert_eq!((cache.hits, cache.misses), (2, 1));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drops_entries_of_words_the_dictionary_learned() {
+        let (dir, path) = setup("learn", "labas L A B A S\n");
+        run(&dir, &path, &["labas", "diena"]).save().unwrap();
+        write(dir.join("lexicon.txt"), "labas L A B A S\ndiena D IE N A\n").unwrap();
+        let cache = run(&dir, &path, &["labas", "diena"]);
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_only_entries_of_the_run() {
+        let (dir, path) = setup("unused", "labas L A B A S\nrytas R I_ T A S\n");
+        run(&dir, &path, &["labas", "rytas"]).save().unwrap();
+        run(&dir, &path, &["labas"]).save().unwrap();
+        let cache = run(&dir, &path, &[]);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.entries.contains_key(&content_hash(b"labas")));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn treats_words_without_variants_as_a_miss() {
+        let (dir, path) = setup("empty", "labas L A B A S\n");
+        let mut cache = run(&dir, &path, &["labas"]);
+        cache.entries.get_mut(&content_hash(b"labas")).unwrap().words[0].variants.clear();
+        let resolvers = resolvers(&dir);
+        let converted = cache.construct(entry("labas"), &TranscriptRules::default(), &resolvers, &EntryOptions { provenance: true, ..EntryOptions::default() }).unwrap();
+        assert_eq!((cache.hits, cache.misses), (0, 2));
+        assert_eq!(converted.words.unwrap()[0].variants.len(), 1);
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_glued_markers() {
+        let (dir, path) = setup("glued", "labas L A B A S\n");
+        let cache = run(&dir, &path, &["labas_pauze"]);
+        assert_eq!(cache.entries[&content_hash(b"labas_pauze")].glued, vec![String::from("labas_pauze")]);
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,9 +2,36 @@ use clap::App;
 use clap::Arg;
 use clap::ArgMatches;
 use std::path::Path;
+use std::path::PathBuf;
+use std::collections::HashSet;
 use logging::level_from_string;
 use regex::Regex;
-use logging::setup_logger;
+use clap::AppSettings;
+use clap::SubCommand;
+use dict_walker::WalkOptions;
+use dict_walker::Config;
+use dict_walker::Error;
+use dict_walker::pipeline::InputOptions;
+use dict_walker::pipeline::OutputFormat;
+use dict_walker::pipeline::ResolverOptions;
+use dict_walker::pipeline::ResolverSpec;
+use dict_walker::pipeline::TranscriptOptions;
+use dict_walker::training_entry::EntryOptions;
+use dict_walker::training_entry::VariantMode;
+use dict_walker::writers::DataDirOptions;
+use dict_walker::filters::EntryFilters;
+use dict_walker::dict_entry::PairingMode;
+use dict_walker::pairing::FilePattern;
+use dict_walker::archive::is_archive;
+use dict_walker::issues::IssueOptions;
+use dict_walker::path_encoding::PathEncoding;
+use dict_walker::traversal::Glob;
+use dict_walker::traversal::SymlinkMode;
+use dict_walker::traversal::TraversalOptions;
+use dict_walker::pairing::PairingPatterns;
+use dict_walker::audio_quality::AnalysisOptions;
+use dict_walker::rate_check::RateCheckOptions;
+use std::str::FromStr;
 
 fn valid_s2s_model(path_str: String) -> Result<(), String> {
     valid_dir(path_str.clone())?;
@@ -23,6 +50,14 @@ fn valid_dir(path_str: String) -> Result<(), String> {
     }
 }
 
+fn valid_dictionary(path_str: String) -> Result<(), String> {
+    if is_archive(Path::new(&path_str)) {
+        Ok(())
+    } else {
+        valid_dir(path_str)
+    }
+}
+
 fn valid_dest_file(path_str: String) -> Result<(), String> {
     let path = Path::new(&path_str);
     if let Some(parent) = path.parent()  {
@@ -47,56 +82,478 @@ fn valid_input_file(path_str: String) -> Result<(), String> {
 }
 
 fn valid_level(log_level_str: String) -> Result<(), String> {
-    match level_from_string(log_level_str.as_ref()) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e)
-    }
+    level_from_string(log_level_str.as_ref()).map(|_| ())
 }
 
 fn extension_list_valid(extension: String) -> Result<(), String> {
     lazy_static! {
-        static ref ext_list_re: Regex = Regex::new(r"[a-zA-Z0-9,]").unwrap();
+        static ref EXT_LIST_RE: Regex = Regex::new(r"[a-zA-Z0-9,]").unwrap();
+    }
+    if EXT_LIST_RE.is_match(extension.as_ref()) {
+        Ok(())
+    } else {
+        Err(format!("\"{}\" is not a valid extension list.", extension))
+    }
+}
+
+fn dictionary_arg() -> Arg<'static, 'static> {
+    Arg::with_name("dictionary")
+        .short("i")
+        .long("dictionary")
+        .value_name("DIRECTORY")
+        .help("Path to the text <-> audio dictionary, a directory or a .tar, .tar.gz, .tgz or .zip archive")
+        .takes_value(true)
+        .validator(valid_dictionary)
+}
+
+fn input_manifest_arg() -> Arg<'static, 'static> {
+    Arg::with_name("input manifest")
+        .long("input-manifest")
+        .value_name("FILE")
+        .help("Read the entries from a TSV (or .csv) file with audio path, transcript path or text, and optional speaker columns instead of walking the dictionary, which then only serves as the root of speaker directories")
+        .takes_value(true)
+        .required(false)
+        .conflicts_with_all(&["audio pattern", "text pattern"])
+        .validator(valid_input_file)
+}
+
+fn output_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("FILE")
+        .help(help)
+        .takes_value(true)
+        .validator(valid_dest_file)
+}
+
+fn force_arg() -> Arg<'static, 'static> {
+    Arg::with_name("force")
+        .short("f")
+        .long("force")
+        .help("Allow overwriting of the output file")
+        .takes_value(false)
+        .required(false)
+}
+
+fn audio_extensions_arg() -> Arg<'static, 'static> {
+    Arg::with_name("audio extensions")
+        .short("a")
+        .long("audio")
+        .value_name("EXTS")
+        .help("comma delimited audio extensions")
+        .takes_value(true)
+        .required(false)
+        .validator(extension_list_valid)
+        .default_value("wav")
+}
+
+fn text_extensions_arg() -> Arg<'static, 'static> {
+    Arg::with_name("text extensions")
+        .short("t")
+        .long("text")
+        .value_name("EXTS")
+        .help("comma delimited text extensions")
+        .takes_value(true)
+        .required(false)
+        .validator(extension_list_valid)
+        .default_value("txt")
+}
+
+fn phoneme_dictionary_arg() -> Arg<'static, 'static> {
+    Arg::with_name("phoneme dictionary")
+        .short("p")
+        .long("phonemes")
+        .value_name("DICTIONARY")
+        .help("path to grapheme-phoneme dictionary")
+        .takes_value(true)
+        .required(false)
+        .validator(valid_input_file)
+}
+
+fn model_arg() -> Arg<'static, 'static> {
+    Arg::with_name("Seq2Seq model folder")
+        .short("m")
+        .long("model")
+        .value_name("FOLDER")
+        .help("Seq2Seq grapheme to phoneme model folder")
+        .takes_value(true)
+        .required(false)
+        .validator(valid_s2s_model)
+}
+
+fn spelling_rules_arg() -> Arg<'static, 'static> {
+    Arg::with_name("spelling rules")
+        .short("s")
+        .long("spelling")
+        .value_name("FILE")
+        .help("TOML file with spelling corrections applied to every transcript")
+        .takes_value(true)
+        .required(false)
+        .validator(valid_input_file)
+}
+
+fn markers_arg() -> Arg<'static, 'static> {
+    Arg::with_name("markers")
+        .long("markers")
+        .value_name("FILE")
+        .help("TOML file defining the transcript markers, their [TAG]s and phoneme ordinals")
+        .takes_value(true)
+        .required(false)
+        .validator(valid_input_file)
+}
+
+fn valid_variant_mode(mode: String) -> Result<(), String> {
+    VariantMode::from_str(&mode).map(|_| ())
+}
+
+fn variants_arg() -> Arg<'static, 'static> {
+    Arg::with_name("variants")
+        .long("variants")
+        .value_name("MODE")
+        .help("pronunciations of words with several dictionary variants: \"first\" or \"all\" (adds a phoneme_lattice)")
+        .takes_value(true)
+        .required(false)
+        .validator(valid_variant_mode)
+        .default_value("first")
+}
+
+fn valid_resolver_chain(chain: String) -> Result<(), String> {
+    for spec in ResolverSpec::parse_chain(&chain)? {
+        match spec {
+            ResolverSpec::Dictionary(path) => valid_input_file(path.to_string_lossy().into_owned())?,
+            ResolverSpec::Tensorflow(path) => valid_s2s_model(path.to_string_lossy().into_owned())?,
+            _ => ()
+        }
+    }
+    Ok(())
+}
+
+fn resolvers_arg() -> Arg<'static, 'static> {
+    Arg::with_name("resolvers")
+        .short("r")
+        .long("resolvers")
+        .value_name("CHAIN")
+        .help("comma delimited resolver chain, e.g. marker,dict:lex1.txt,dict:lex2.txt,tf:model/,deadend (replaces -p and -m)")
+        .takes_value(true)
+        .required(false)
+        .conflicts_with_all(&["phoneme dictionary", "Seq2Seq model folder"])
+        .validator(valid_resolver_chain)
+}
+
+fn valid_pairing(mode: String) -> Result<(), String> {
+    PairingMode::from_str(&mode).map(|_| ())
+}
+
+fn pairing_arg() -> Arg<'static, 'static> {
+    Arg::with_name("pairing")
+        .long("pairing")
+        .value_name("MODE")
+        .help("how audio and transcripts are paired: directory (same stem in the same directory) or global (same stem anywhere, stems must be unique)")
+        .takes_value(true)
+        .required(false)
+        .default_value("directory")
+        .validator(valid_pairing)
+}
+
+fn valid_pattern(pattern: String) -> Result<(), String> {
+    FilePattern::from_str(&pattern).map(|_| ())
+}
+
+/// Template or `re:` pairing rules, see `dict_walker::pairing::FilePattern`.
+fn pattern_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("audio pattern")
+            .long("audio-pattern")
+            .value_name("PATTERN")
+            .help("where the audio of an entry lies relative to the dictionary, e.g. {dir}/wav/{id}.wav or re:(?P<dir>.*)/(?P<id>\\w+)\\.wav; replaces --pairing and the extension lists")
+            .takes_value(true)
+            .required(false)
+            .requires("text pattern")
+            .validator(valid_pattern),
+        Arg::with_name("text pattern")
+            .long("text-pattern")
+            .value_name("PATTERN")
+            .help("where the transcript of an entry lies relative to the dictionary, e.g. {dir}/txt/{id}.txt or {dir}/{id}.trans.txt")
+            .takes_value(true)
+            .required(false)
+            .requires("audio pattern")
+            .validator(valid_pattern)
+    ]
+}
+
+fn valid_glob_list(list: String) -> Result<(), String> {
+    list.split(',').try_for_each(|v| Glob::from_str(v).map(|_| ()))
+}
+
+fn valid_symlink_mode(mode: String) -> Result<(), String> {
+    SymlinkMode::from_str(&mode).map(|_| ())
+}
+
+/// Which files the walk visits, see `dict_walker::traversal::TraversalOptions`.
+fn traversal_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("include")
+            .long("include")
+            .value_name("GLOBS")
+            .help("comma delimited globs relative to the dictionary, only matching files (or files in matching directories) are walked, e.g. spk*/**/*.wav,*.txt")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_glob_list),
+        Arg::with_name("exclude")
+            .long("exclude")
+            .value_name("GLOBS")
+            .help("comma delimited globs of files and directories left out of the walk, e.g. .*,backup/,*_rejected.*; .dictwalkerignore files list more in every directory")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_glob_list),
+        Arg::with_name("max depth")
+            .long("max-depth")
+            .value_name("N")
+            .help("directory levels walked below the dictionary, 0 taking only the files directly in it")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_count),
+        Arg::with_name("symlinks")
+            .long("symlinks")
+            .value_name("MODE")
+            .help("follow (skipping directories already walked) or skip symbolic links")
+            .takes_value(true)
+            .required(false)
+            .default_value("follow")
+            .validator(valid_symlink_mode)
+    ]
+}
+
+fn valid_output_format(format: String) -> Result<(), String> {
+    OutputFormat::from_str(&format).map(|_| ())
+}
+
+fn valid_speaker_level(level: String) -> Result<(), String> {
+    match level.parse::<usize>() {
+        Ok(v) if v > 0 => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid directory level, expected a positive number.", level))
     }
-    match ext_list_re.is_match(extension.as_ref()) {
-        true => Ok(()),
-        false => Err(format!("\"{}\" is not a valid extension list.", extension))
+}
+
+fn speaker_level_arg() -> Arg<'static, 'static> {
+    Arg::with_name("speaker level")
+        .long("speaker-level")
+        .value_name("N")
+        .help("directory level below the dictionary naming the speaker (espnet and kaldi output, speaking rate check), 1 being the top level")
+        .takes_value(true)
+        .required(false)
+        .default_value("1")
+        .validator(valid_speaker_level)
+}
+
+fn probe_audio_arg() -> Arg<'static, 'static> {
+    Arg::with_name("probe audio")
+        .long("probe-audio")
+        .help("Read the WAV header of every audio file, adding its duration and format to the output and skipping defective files")
+        .takes_value(false)
+        .required(false)
+}
+
+fn valid_seconds(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid number, expected a non-negative decimal.", value))
     }
 }
 
+fn valid_rate(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid rate, expected a positive number per second.", value))
+    }
+}
+
+fn valid_percent(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if (0.0..=100.0).contains(&v) => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid percentage, expected a decimal from 0 to 100.", value))
+    }
+}
+
+fn valid_ratio(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid ratio, expected a decimal from 0 to 1.", value))
+    }
+}
+
+fn valid_z_score(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid score, expected a positive decimal.", value))
+    }
+}
+
+fn valid_full_scale(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid level, expected a decimal from 0 to 1, full scale being 1.", value))
+    }
+}
+
+fn valid_count(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(_) => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid count, expected a non-negative whole number.", value))
+    }
+}
+
+fn valid_decibels(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if v <= 0.0 => Ok(()),
+        _ => Err(format!("\"{}\" is not a valid level, expected dBFS at or below 0.", value))
+    }
+}
+
+fn filter_arg(name: &'static str, long: &'static str, value_name: &'static str, help: &'static str, validator: Validator) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(long)
+        .value_name(value_name)
+        .help(help)
+        .takes_value(true)
+        .required(false)
+        .validator(validator)
+}
+
+/// Selection rules, entries failing one are dropped before they are written.
+fn filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        filter_arg("min duration", "min-duration", "SECONDS", "drop entries with shorter audio", valid_seconds),
+        filter_arg("max duration", "max-duration", "SECONDS", "drop entries with longer audio", valid_seconds),
+        filter_arg("sample rate", "sample-rate", "HZ", "drop entries with audio of another sample rate", valid_count),
+        Arg::with_name("mono")
+            .long("mono")
+            .help("Drop entries with audio of more than one channel")
+            .takes_value(false)
+            .required(false),
+        filter_arg("min words", "min-words", "N", "drop entries with fewer transcript words", valid_count),
+        filter_arg("max words", "max-words", "N", "drop entries with more transcript words", valid_count),
+        filter_arg("max phoneme rate", "max-phoneme-rate", "N", "drop entries with more phonemes per second of audio", valid_rate),
+        filter_arg("max clipping", "max-clipping", "PERCENT", "drop entries with a larger share of clipped samples", valid_percent),
+        filter_arg("max dc offset", "max-dc-offset", "LEVEL", "drop entries with a larger DC offset, full scale being 1", valid_full_scale),
+        filter_arg("min rms", "min-rms", "DBFS", "drop entries with a lower RMS level", valid_decibels)
+            .allow_hyphen_values(true),
+        filter_arg("max silence ratio", "max-silence-ratio", "RATIO", "drop entries with a larger share of silence, 0 to 1", valid_ratio)
+    ]
+}
+
+/// Sample analysis, see `dict_walker::audio_quality`.
+fn analysis_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("analyze audio")
+            .long("analyze-audio")
+            .help("Decode the samples of every 16-bit PCM or 32-bit float WAV and add clipping, DC offset, levels and silence to the output")
+            .takes_value(false)
+            .required(false),
+        Arg::with_name("silence threshold")
+            .long("silence-threshold")
+            .value_name("DBFS")
+            .help("RMS level of 10 ms windows below which the audio analysis counts them as silence")
+            .takes_value(true)
+            .required(false)
+            .default_value("-40")
+            .allow_hyphen_values(true)
+            .validator(valid_decibels)
+    ]
+}
+
+/// Speaking rate QA, see `dict_walker::rate_check`.
+fn rate_check_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("rate report")
+            .long("rate-report")
+            .value_name("FILE")
+            .help("Check speaking rates per speaker and write the likely transcript/audio mismatches to this TSV file")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_dest_file),
+        Arg::with_name("rate threshold")
+            .long("rate-threshold")
+            .value_name("SCORE")
+            .help("modified z-score of phonemes per second, from the speaker median in MADs, above which an utterance is flagged")
+            .takes_value(true)
+            .required(false)
+            .default_value("3.5")
+            .validator(valid_z_score),
+        Arg::with_name("exclude rate outliers")
+            .long("exclude-rate-outliers")
+            .help("Leave utterances flagged by the speaking rate check out of the output")
+            .takes_value(false)
+            .required(false)
+    ]
+}
+
+/// Incremental re-runs, see `dict_walker::cache::EntryCache`.
+fn cache_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("cache")
+            .long("cache")
+            .help("Keep the converted transcripts in OUTPUT.cache and only convert added or changed ones on later runs")
+            .takes_value(false)
+            .required(false),
+        Arg::with_name("cache file")
+            .long("cache-file")
+            .value_name("FILE")
+            .help("Like --cache, with the cache at FILE")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_dest_file)
+    ]
+}
+
+fn issue_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("keep going")
+            .long("keep-going")
+            .help("Skip files and entries with collisions, undecodable transcripts, non-Unicode paths or unresolved words instead of stopping at the first one")
+            .takes_value(false)
+            .required(false),
+        Arg::with_name("issue report")
+            .long("issue-report")
+            .value_name("FILE")
+            .help("Write every problem found in the dictionary to FILE as JSON lines")
+            .takes_value(true)
+            .required(false)
+            .validator(valid_dest_file)
+    ]
+}
+
+fn valid_path_encoding(encoding: String) -> Result<(), String> {
+    PathEncoding::from_str(&encoding).map(|_| ())
+}
+
+fn path_encoding_arg() -> Arg<'static, 'static> {
+    Arg::with_name("path encoding")
+        .long("path-encoding")
+        .value_name("ENCODING")
+        .help("how paths that are not UTF-8 are written: escape (prefixed with \"escaped:\", other bytes as \\xNN and backslashes doubled, lossless) or lossy (other bytes as U+FFFD)")
+        .takes_value(true)
+        .required(false)
+        .default_value("escape")
+        .validator(valid_path_encoding)
+}
+
+fn provenance_arg() -> Arg<'static, 'static> {
+    Arg::with_name("provenance")
+        .long("provenance")
+        .help("Record the resolver which produced every word")
+        .takes_value(false)
+        .required(false)
+}
+
 pub fn get_args() -> ArgMatches<'static> {
-    let matches = App::new("Audio dictionary walker")
+    App::new("Audio dictionary walker")
         .version("0.1.0")
         .author("DT <deltakowsz@gmail.com>")
         .about("Walks the text <-> audio dictionary and produces a JSON with phonemes")
-        .arg(
-            Arg::with_name("dictionary")
-                .short("i")
-                .long("dictionary")
-                .value_name("DIRECTORY")
-                .help("Path to the text <-> audio dictionary")
-                .takes_value(true)
-                .required(true)
-                .validator(valid_dir)
-        )
-        .arg(
-            Arg::with_name("output")
-                .short("o")
-                .long("output")
-                .value_name("FILE")
-                .help("Path to output json file")
-                .takes_value(true)
-                .required(true)
-                .validator(valid_dest_file)
-                .default_value("./output.json")
-        )
-        .arg(
-            Arg::with_name("force")
-                .short("f")
-                .long("force")
-                .help("Allow overwriting of the output file")
-                .takes_value(false)
-                .required(false)
-        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
         .arg(
             Arg::with_name("log level")
                 .short("l")
@@ -105,68 +562,515 @@ pub fn get_args() -> ArgMatches<'static> {
                 .help("logging level")
                 .takes_value(true)
                 .required(false)
+                .global(true)
                 .validator(valid_level)
                 .default_value("info")
         )
         .arg(
-            Arg::with_name("audio extensions")
-                .short("a")
-                .long("audio")
-                .value_name("EXTS")
-                .help("comma delimited audio extensions")
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("TOML file with default values for any of the options")
                 .takes_value(true)
                 .required(false)
-                .validator(extension_list_valid)
-                .default_value("wav")
+                .global(true)
+                .validator(valid_input_file)
         )
         .arg(
-            Arg::with_name("text extensions")
-                .short("t")
-                .long("text")
-                .value_name("EXTS")
-                .help("comma delimited text extensions")
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("[profile.NAME] table of the configuration file to apply")
                 .takes_value(true)
                 .required(false)
-                .validator(extension_list_valid)
-                .default_value("txt")
+                .global(true)
+                .requires("config")
         )
-        .arg(
-            Arg::with_name("phoneme dictionary")
-                .short("p")
-                .long("phonemes")
-                .value_name("DICTIONARY")
-                .help("path to grapheme-phoneme dictionary")
-                .takes_value(true)
-                .required(false)
-                .validator(valid_input_file)
+        .subcommand(
+            SubCommand::with_name("walk")
+                .about("Walks the dictionary and writes a JSON with phonemes")
+                .arg(dictionary_arg())
+                .arg(output_arg("Path to the output file, or directory for espnet and kaldi [default: ./output.json, ./data for directories]"))
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("output layout: json (one array), jsonl (one entry per line, written as it is built), nemo (manifest lines), espnet or kaldi (data directories at --output)")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("json")
+                        .validator(valid_output_format)
+                )
+                .arg(speaker_level_arg())
+                .arg(force_arg())
+                .arg(audio_extensions_arg())
+                .arg(text_extensions_arg())
+                .arg(pairing_arg())
+                .arg(input_manifest_arg())
+                .args(&issue_args())
+                .arg(path_encoding_arg())
+                .args(&traversal_args())
+                .args(&pattern_args())
+                .arg(probe_audio_arg())
+                .args(&analysis_args())
+                .args(&filter_args())
+                .args(&rate_check_args())
+                .args(&cache_args())
+                .arg(spelling_rules_arg())
+                .arg(markers_arg())
+                .arg(phoneme_dictionary_arg())
+                .arg(model_arg())
+                .arg(resolvers_arg())
+                .arg(variants_arg())
+                .arg(provenance_arg())
         )
-        .arg(
-            Arg::with_name("Seq2Seq model folder")
-                .short("m")
-                .long("model")
-                .value_name("FOLDER")
-                .help("Seq2Seq grapheme to phoneme model folder")
-                .takes_value(true)
-                .required(false)
-                .validator(valid_s2s_model)
+        .subcommand(
+            SubCommand::with_name("g2p")
+                .about("Resolves words through the resolver chain, reads stdin when no words are given")
+                .arg(
+                    Arg::with_name("words")
+                        .value_name("WORD")
+                        .help("words to resolve")
+                        .multiple(true)
+                        .required(false)
+                )
+                .arg(spelling_rules_arg())
+                .arg(markers_arg())
+                .arg(phoneme_dictionary_arg())
+                .arg(model_arg())
+                .arg(resolvers_arg())
+                .arg(variants_arg())
+                .arg(provenance_arg())
+        )
+        .subcommand(
+            SubCommand::with_name("lexicon")
+                .about("Checks, sorts and deduplicates a grapheme-phoneme dictionary")
+                .arg(phoneme_dictionary_arg().required(true))
+                .arg(output_arg("Path to write the cleaned dictionary to"))
+                .arg(force_arg())
+                .arg(
+                    dictionary_arg()
+                        .required(false)
+                        .help("List words of this text <-> audio dictionary missing from the grapheme-phoneme dictionary")
+                )
+                .arg(audio_extensions_arg())
+                .arg(text_extensions_arg())
+                .arg(pairing_arg())
+                .arg(input_manifest_arg())
+                .args(&issue_args())
+                .arg(path_encoding_arg())
+                .args(&traversal_args())
+                .args(&pattern_args())
+                .arg(spelling_rules_arg())
+                .arg(markers_arg())
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Reports on an existing output file")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("FILE")
+                        .help("Path to the output json file to report on")
+                        .validator(valid_input_file)
+                )
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks a dictionary tree without writing any output")
+                .arg(dictionary_arg())
+                .arg(audio_extensions_arg())
+                .arg(text_extensions_arg())
+                .arg(pairing_arg())
+                .arg(input_manifest_arg())
+                .args(&issue_args())
+                .arg(path_encoding_arg())
+                .args(&traversal_args())
+                .args(&pattern_args())
+                .arg(probe_audio_arg())
+                .arg(spelling_rules_arg())
+                .arg(markers_arg())
+                .arg(phoneme_dictionary_arg())
+                .arg(model_arg())
+                .arg(resolvers_arg())
+        )
+        .get_matches()
+}
+
+type Validator = fn(String) -> Result<(), String>;
+
+enum OptionKind {
+    Path(Validator),
+    Value(Validator),
+    Flag
+}
+
+/// Argument name, configuration key and kind of every option a configuration file may set.
+/// Keys are the long names of the command line options.
+const OPTIONS: &[(&str, &str, OptionKind)] = &[
+    ("dictionary", "dictionary", OptionKind::Path(valid_dictionary)),
+    ("output", "output", OptionKind::Path(valid_dest_file)),
+    ("format", "format", OptionKind::Value(valid_output_format)),
+    ("force", "force", OptionKind::Flag),
+    ("log level", "level", OptionKind::Value(valid_level)),
+    ("audio extensions", "audio", OptionKind::Value(extension_list_valid)),
+    ("pairing", "pairing", OptionKind::Value(valid_pairing)),
+    ("input manifest", "input-manifest", OptionKind::Path(valid_input_file)),
+    ("audio pattern", "audio-pattern", OptionKind::Value(valid_pattern)),
+    ("text pattern", "text-pattern", OptionKind::Value(valid_pattern)),
+    ("text extensions", "text", OptionKind::Value(extension_list_valid)),
+    ("spelling rules", "spelling", OptionKind::Path(valid_input_file)),
+    ("markers", "markers", OptionKind::Path(valid_input_file)),
+    ("phoneme dictionary", "phonemes", OptionKind::Path(valid_input_file)),
+    ("Seq2Seq model folder", "model", OptionKind::Path(valid_s2s_model)),
+    ("resolvers", "resolvers", OptionKind::Value(valid_resolver_chain)),
+    ("variants", "variants", OptionKind::Value(valid_variant_mode)),
+    ("provenance", "provenance", OptionKind::Flag),
+    ("probe audio", "probe-audio", OptionKind::Flag),
+    ("min duration", "min-duration", OptionKind::Value(valid_seconds)),
+    ("max duration", "max-duration", OptionKind::Value(valid_seconds)),
+    ("sample rate", "sample-rate", OptionKind::Value(valid_count)),
+    ("mono", "mono", OptionKind::Flag),
+    ("min words", "min-words", OptionKind::Value(valid_count)),
+    ("max words", "max-words", OptionKind::Value(valid_count)),
+    ("max phoneme rate", "max-phoneme-rate", OptionKind::Value(valid_rate)),
+    ("max clipping", "max-clipping", OptionKind::Value(valid_percent)),
+    ("max dc offset", "max-dc-offset", OptionKind::Value(valid_full_scale)),
+    ("min rms", "min-rms", OptionKind::Value(valid_decibels)),
+    ("max silence ratio", "max-silence-ratio", OptionKind::Value(valid_ratio)),
+    ("analyze audio", "analyze-audio", OptionKind::Flag),
+    ("silence threshold", "silence-threshold", OptionKind::Value(valid_decibels)),
+    ("rate report", "rate-report", OptionKind::Path(valid_dest_file)),
+    ("rate threshold", "rate-threshold", OptionKind::Value(valid_z_score)),
+    ("exclude rate outliers", "exclude-rate-outliers", OptionKind::Flag),
+    ("cache", "cache", OptionKind::Flag),
+    ("cache file", "cache-file", OptionKind::Path(valid_dest_file)),
+    ("keep going", "keep-going", OptionKind::Flag),
+    ("path encoding", "path-encoding", OptionKind::Value(valid_path_encoding)),
+    ("include", "include", OptionKind::Value(valid_glob_list)),
+    ("exclude", "exclude", OptionKind::Value(valid_glob_list)),
+    ("max depth", "max-depth", OptionKind::Value(valid_count)),
+    ("symlinks", "symlinks", OptionKind::Value(valid_symlink_mode)),
+    ("issue report", "issue-report", OptionKind::Path(valid_dest_file)),
+    ("speaker level", "speaker-level", OptionKind::Value(valid_speaker_level)),
+    ("input", "input", OptionKind::Path(valid_input_file))
+];
+
+/// Arguments of each subcommand which are taken from the configuration file when not given.
+/// `lexicon` gives `--dictionary` and `--output` a different meaning, so those stay command line only.
+fn configurable_options(subcommand: &str) -> &'static [&'static str] {
+    match subcommand {
+        "walk" => &["dictionary", "output", "format", "speaker level", "force", "log level", "audio extensions", "text extensions", "pairing", "input manifest", "keep going", "issue report", "path encoding", "include", "exclude", "max depth", "symlinks", "audio pattern", "text pattern", "probe audio", "min duration", "max duration", "sample rate", "mono", "min words", "max words", "max phoneme rate", "max clipping", "max dc offset", "min rms", "max silence ratio", "analyze audio", "silence threshold", "rate report", "rate threshold", "exclude rate outliers", "cache", "cache file", "spelling rules", "markers", "phoneme dictionary", "Seq2Seq model folder", "resolvers", "variants", "provenance"],
+        "g2p" => &["log level", "spelling rules", "markers", "phoneme dictionary", "Seq2Seq model folder", "resolvers", "variants", "provenance"],
+        "lexicon" => &["log level", "audio extensions", "text extensions", "pairing", "input manifest", "keep going", "issue report", "path encoding", "include", "exclude", "max depth", "symlinks", "audio pattern", "text pattern", "spelling rules", "markers", "phoneme dictionary"],
+        "stats" => &["log level", "input"],
+        "validate" => &["log level", "dictionary", "audio extensions", "text extensions", "pairing", "input manifest", "keep going", "issue report", "path encoding", "include", "exclude", "max depth", "symlinks", "audio pattern", "text pattern", "probe audio", "spelling rules", "markers", "phoneme dictionary", "Seq2Seq model folder", "resolvers"],
+        _ => &["log level"]
+    }
+}
+
+fn option(name: &str) -> &'static (&'static str, &'static str, OptionKind) {
+    OPTIONS.iter()
+        .find(|v| v.0 == name)
+        .unwrap_or_else(|| panic!("\"{}\" is not a configurable option", name))
+}
+
+/// Subcommand arguments merged with the configuration file.
+/// Options given on the command line win over the selected profile, which wins over top-level keys,
+/// which win over the built-in defaults.
+pub struct Settings<'a> {
+    matches: &'a ArgMatches<'a>,
+    configurable: &'static [&'static str],
+    config: Config
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(subcommand: &str, matches: &'a ArgMatches<'a>) -> dict_walker::Result<Settings<'a>> {
+        let config = match matches.value_of("config") {
+            Some(path) => Config::load(Path::new(path), matches.value_of("profile"))?,
+            None => Config::default()
+        };
+        let keys: Vec<&str> = OPTIONS.iter().map(|v| v.1).collect();
+        config.check_keys(&keys)?;
+
+        let settings = Settings { matches, configurable: configurable_options(subcommand), config };
+        settings.check_values()?;
+        Ok(settings)
+    }
+
+    /// Type checks and validates the configured options of this subcommand which the command line leaves to the
+    /// configuration, after the profile has been merged over the top-level keys.
+    fn check_values(&self) -> dict_walker::Result<()> {
+        for &name in self.configurable.iter().filter(|v| self.matches.occurrences_of(v) == 0) {
+            let (_, key, ref kind) = *option(name);
+            let value = match *kind {
+                OptionKind::Path(validator) => self.config.get_path(key)?.map(|v| (v, validator)),
+                OptionKind::Value(validator) => self.config.get_str(key)?.map(|v| (v, validator)),
+                OptionKind::Flag => {
+                    self.config.get_bool(key)?;
+                    None
+                }
+            };
+            if let Some((v, validator)) = value {
+                validator(v).map_err(|e| self.config.invalid(key, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self) -> &ArgMatches<'a> {
+        self.matches
+    }
+
+    pub fn value_of(&self, name: &str) -> dict_walker::Result<Option<String>> {
+        if self.matches.occurrences_of(name) > 0 || !self.configurable.contains(&name) {
+            return Ok(self.matches.value_of(name).map(String::from));
+        }
+
+        let (_, key, ref kind) = *option(name);
+        let value = match *kind {
+            OptionKind::Path(_) => self.config.get_path(key)?,
+            OptionKind::Value(_) => self.config.get_str(key)?,
+            OptionKind::Flag => return Err(self.config.invalid(key, "expected a value option"))
+        };
+        match value {
+            Some(v) => Ok(Some(v)),
+            None => Ok(self.matches.value_of(name).map(String::from))
+        }
+    }
+
+    pub fn required(&self, name: &str) -> dict_walker::Result<String> {
+        match self.value_of(name)? {
+            Some(v) => Ok(v),
+            None => Err(Error::MissingOption { key: option(name).1.to_string() })
+        }
+    }
+
+    pub fn is_present(&self, name: &str) -> dict_walker::Result<bool> {
+        if self.matches.is_present(name) || !self.configurable.contains(&name) {
+            return Ok(self.matches.is_present(name));
+        }
+        Ok(self.config.get_bool(option(name).1)?.unwrap_or(false))
+    }
+}
+
+fn extension_set(list: &str) -> HashSet<String> {
+    list.split(',').map(String::from).collect()
+}
+
+fn glob_list(settings: &Settings, name: &str) -> dict_walker::Result<Vec<Glob>> {
+    Ok(match settings.value_of(name)? {
+        Some(list) => list.split(',').map(|v| Glob::from_str(v).unwrap()).collect(),
+        None => Vec::new()
+    })
+}
+
+fn traversal_options(settings: &Settings) -> dict_walker::Result<TraversalOptions> {
+    Ok(TraversalOptions {
+        include: glob_list(settings, "include")?,
+        exclude: glob_list(settings, "exclude")?,
+        max_depth: parsed(settings, "max depth")?,
+        symlinks: parsed(settings, "symlinks")?.unwrap_or_default()
+    })
+}
 
+pub fn input_options(settings: &Settings) -> dict_walker::Result<InputOptions> {
+    let manifest = settings.value_of("input manifest")?.map(PathBuf::from);
+    Ok(InputOptions {
+        dictionary: match (settings.value_of("dictionary")?, &manifest) {
+            (Some(v), _) => PathBuf::from(v),
+            (None, Some(v)) => v.parent().map(Path::to_path_buf).unwrap_or_default(),
+            (None, None) => PathBuf::from(settings.required("dictionary")?)
+        },
+        manifest,
+        audio_exts: extension_set(&settings.required("audio extensions")?),
+        text_exts: extension_set(&settings.required("text extensions")?),
+        pairing: parsed(settings, "pairing")?.unwrap_or_default(),
+        traversal: traversal_options(settings)?,
+        patterns: match (parsed(settings, "audio pattern")?, parsed(settings, "text pattern")?) {
+            (Some(audio), Some(text)) => Some(PairingPatterns { audio, text }),
+            (None, None) => None,
+            (Some(_), None) => return Err(Error::MissingOption { key: String::from("text-pattern") }),
+            (None, Some(_)) => return Err(Error::MissingOption { key: String::from("audio-pattern") })
+        },
+        probe_audio: settings.is_present("probe audio")?,
+        analysis: analysis_options(settings)?
+    })
+}
 
-    setup_logger(
-        level_from_string(
-            matches.value_of("log level").unwrap()
-        ).unwrap()
-    ).expect("Failed to initialize logging.");
+pub fn transcript_options(settings: &Settings) -> dict_walker::Result<TranscriptOptions> {
+    Ok(TranscriptOptions {
+        spelling_rules: settings.value_of("spelling rules")?.map(PathBuf::from),
+        markers: settings.value_of("markers")?.map(PathBuf::from)
+    })
+}
 
-    if !matches.is_present("force") {
-        let path = Path::new(matches.value_of("output").unwrap());
-        if path.exists() {
-            error!("Output file already exists! Use the --force (or -f) to force overwriting of the output file.");
-            panic!();
+/// The `--resolvers` chain if given, the classic chain built from `-p` and `-m` otherwise.
+pub fn resolver_options(settings: &Settings) -> dict_walker::Result<ResolverOptions> {
+    if let Some(chain) = settings.value_of("resolvers")? {
+        // already checked by the argument validator or the configuration value checks
+        return Ok(ResolverOptions { chain: ResolverSpec::parse_chain(&chain).unwrap() });
+    }
+    Ok(ResolverOptions::new(
+        settings.value_of("phoneme dictionary")?.map(PathBuf::from),
+        settings.value_of("Seq2Seq model folder")?.map(PathBuf::from)
+    ))
+}
+
+pub fn entry_options(settings: &Settings) -> dict_walker::Result<EntryOptions> {
+    Ok(EntryOptions {
+        variants: VariantMode::from_str(&settings.required("variants")?).unwrap(),
+        provenance: settings.is_present("provenance")?,
+        path_encoding: parsed(settings, "path encoding")?.unwrap_or_default()
+    })
+}
+
+/// Sample analysis parameters, also when only the quality thresholds ask for it.
+fn analysis_options(settings: &Settings) -> dict_walker::Result<Option<AnalysisOptions>> {
+    let threshold = parsed(settings, "silence threshold")?;
+    let analysis = AnalysisOptions {
+        silence_threshold_db: threshold.unwrap_or_else(|| AnalysisOptions::default().silence_threshold_db)
+    };
+    if settings.is_present("analyze audio")? || filter_options(settings)?.needs_quality() {
+        Ok(Some(analysis))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parses an already validated option.
+fn parsed<T: FromStr>(settings: &Settings, name: &str) -> dict_walker::Result<Option<T>> {
+    Ok(settings.value_of(name)?.map(|v| match v.parse() {
+        Ok(v) => v,
+        Err(_) => unreachable!("option \"{}\" was not validated", name)
+    }))
+}
+
+pub fn filter_options(settings: &Settings) -> dict_walker::Result<EntryFilters> {
+    Ok(EntryFilters {
+        min_duration: parsed(settings, "min duration")?,
+        max_duration: parsed(settings, "max duration")?,
+        sample_rate: parsed(settings, "sample rate")?,
+        mono: settings.is_present("mono")?,
+        min_words: parsed(settings, "min words")?,
+        max_words: parsed(settings, "max words")?,
+        max_phoneme_rate: parsed(settings, "max phoneme rate")?,
+        max_clipping_percent: parsed(settings, "max clipping")?,
+        max_dc_offset: parsed(settings, "max dc offset")?,
+        min_rms_dbfs: parsed(settings, "min rms")?,
+        max_silence_ratio: parsed(settings, "max silence ratio")?
+    })
+}
+
+/// `--cache-file`, or `OUTPUT.cache` next to the output for `--cache`.
+/// `--output` of `walk`, defaulting to a file or directory depending on the format.
+fn output_path(settings: &Settings) -> dict_walker::Result<PathBuf> {
+    Ok(match settings.value_of("output")? {
+        Some(v) => PathBuf::from(v),
+        None => PathBuf::from(OutputFormat::from_str(&settings.required("format")?).unwrap().default_output())
+    })
+}
+
+fn cache_path(settings: &Settings) -> dict_walker::Result<Option<PathBuf>> {
+    if let Some(path) = settings.value_of("cache file")? {
+        return Ok(Some(PathBuf::from(path)));
+    }
+    if !settings.is_present("cache")? {
+        return Ok(None);
+    }
+    let output = output_path(settings)?;
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".cache");
+    Ok(Some(output.with_file_name(name)))
+}
+
+pub fn issue_options(settings: &Settings) -> dict_walker::Result<IssueOptions> {
+    Ok(IssueOptions {
+        keep_going: settings.is_present("keep going")?,
+        report: settings.value_of("issue report")?.map(PathBuf::from),
+        path_encoding: parsed(settings, "path encoding")?.unwrap_or_default()
+    })
+}
+
+pub fn walk_options(settings: &Settings) -> dict_walker::Result<WalkOptions> {
+    Ok(WalkOptions {
+        input: input_options(settings)?,
+        transcript: transcript_options(settings)?,
+        resolvers: resolver_options(settings)?,
+        entry: entry_options(settings)?,
+        output: output_path(settings)?,
+        format: OutputFormat::from_str(&settings.required("format")?).unwrap(),
+        filters: filter_options(settings)?,
+        rate_check: RateCheckOptions {
+            report: settings.value_of("rate report")?.map(PathBuf::from),
+            threshold: parsed(settings, "rate threshold")?.unwrap_or_else(|| RateCheckOptions::default().threshold),
+            exclude: settings.is_present("exclude rate outliers")?
+        },
+        data_dir: DataDirOptions {
+            speaker_level: settings.required("speaker level")?.parse().unwrap()
+        },
+        cache: cache_path(settings)?,
+        issues: issue_options(settings)?,
+        force: settings.is_present("force")?
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid(validator: Validator, value: &str) -> bool {
+        validator(String::from(value)).is_ok()
+    }
+
+    #[test]
+    fn takes_positive_rates() {
+        for value in ["15", "0.5"].iter() {
+            assert!(valid(valid_rate, value), "{}", value);
+        }
+        for value in ["0", "-2", "inf", "NaN"].iter() {
+            assert!(!valid(valid_rate, value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn bounds_percentages() {
+        for value in ["0", "0.5", "100"].iter() {
+            assert!(valid(valid_percent, value), "{}", value);
+        }
+        for value in ["-1", "100.5", "inf", "NaN", "5%"].iter() {
+            assert!(!valid(valid_percent, value), "{}", value);
         }
+    }
 
+    #[test]
+    fn bounds_ratios() {
+        for value in ["0", "0.25", "1"].iter() {
+            assert!(valid(valid_ratio, value), "{}", value);
+        }
+        for value in ["-0.1", "1.5", "50", "NaN"].iter() {
+            assert!(!valid(valid_ratio, value), "{}", value);
+        }
     }
 
-    matches
-}
\ No newline at end of file
+    #[test]
+    fn bounds_full_scale_levels() {
+        for value in ["0", "0.01", "1"].iter() {
+            assert!(valid(valid_full_scale, value), "{}", value);
+        }
+        for value in ["-0.01", "1.5", "inf", "NaN"].iter() {
+            assert!(!valid(valid_full_scale, value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn takes_positive_z_scores() {
+        for value in ["3.5", "0.1", "10"].iter() {
+            assert!(valid(valid_z_score, value), "{}", value);
+        }
+        for value in ["0", "-3.5", "inf", "NaN", "high"].iter() {
+            assert!(!valid(valid_z_score, value), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use cli_api::Settings;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use cli_api::input_options;
+use cli_api::resolver_options;
+use cli_api::transcript_options;
+use cli_api::entry_options;
+use cli_api::walk_options;
+use cli_api::issue_options;
+use dict_walker::Error;
+use dict_walker::Result;
+use dict_walker::Lexicon;
+use dict_walker::TrainingEntry;
+use dict_walker::training_entry::EntryOptions;
+use dict_walker::training_entry::VariantMode;
+use dict_walker::pipeline;
+use dict_walker::issues::IssueLog;
+use dict_walker::stats::DatasetStats;
+
+const STDIN: &str = "<stdin>";
+const STDOUT: &str = "<stdout>";
+const ARGS: &str = "<words>";
+
+pub fn walk(settings: &Settings) -> Result<()> {
+    let count = pipeline::run(&walk_options(settings)?)?;
+    info!("Done. Wrote {} entries.", count);
+    Ok(())
+}
+
+pub fn g2p(settings: &Settings) -> Result<()> {
+    let rules = pipeline::load_transcript_rules(&transcript_options(settings)?)?;
+    let resolvers = pipeline::build_resolvers(&resolver_options(settings)?, &rules.markers)?;
+    let entry_options = entry_options(settings)?;
+    let words: Vec<String> = match settings.matches().values_of("words") {
+        Some(v) => v.map(String::from).collect(),
+        None => {
+            let mut words = Vec::new();
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = line.map_err(|e| Error::io(STDIN, e))?;
+                words.extend(line.split_whitespace().map(String::from));
+            }
+            words
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for word in words {
+        let transcript = TrainingEntry::tokenize(word.clone(), &rules);
+        let resolution = TrainingEntry::convert_to_variants(&transcript, &rules, &resolvers, Path::new(ARGS))?;
+        let phonemes = match entry_options.variants {
+            VariantMode::First => resolution.phonemes().iter().map(|ph| ph.to_string()).collect(),
+            VariantMode::All => TrainingEntry::render_lattice(&resolution.segments)
+        };
+        if entry_options.provenance {
+            let sources: Vec<String> = resolution.words.iter().map(|v| v.resolver.clone()).collect();
+            writeln!(out, "{}\t{}\t{}", word, phonemes, sources.join(",")).map_err(|e| Error::io(STDOUT, e))?;
+        } else {
+            writeln!(out, "{}\t{}", word, phonemes).map_err(|e| Error::io(STDOUT, e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn lexicon(settings: &Settings) -> Result<()> {
+    let path = settings.required("phoneme dictionary")?;
+    let path = Path::new(&path);
+    let mut lexicon = Lexicon::load(path)?;
+    let rules = pipeline::load_transcript_rules(&transcript_options(settings)?)?;
+
+    for (line, entry) in lexicon.malformed.iter() {
+        warn!("Line {}: cannot parse \"{}\" as a dictionary entry", line, entry);
+    }
+    for entry in lexicon.entries.iter() {
+        let invalid = entry.invalid_symbols(&rules.markers);
+        if !invalid.is_empty() {
+            warn!("Word \"{}\" uses unknown phonemes {:?}", entry.word, invalid);
+        }
+    }
+
+    if settings.value_of("dictionary")?.is_some() || settings.value_of("input manifest")?.is_some() {
+        let known = lexicon.words();
+        let mut missing: BTreeSet<String> = BTreeSet::new();
+        let mut issues = IssueLog::new(&issue_options(settings)?);
+        for entry in pipeline::collect_entries(&input_options(settings)?, &mut issues)? {
+            for word in TrainingEntry::tokenize(entry.transcript, &rules).words() {
+                let word = word.text.to_lowercase();
+                if !known.contains(word.as_str()) {
+                    missing.insert(word);
+                }
+            }
+        }
+        for word in missing.iter() {
+            info!("Missing from dictionary: \"{}\"", word);
+        }
+        info!("{} words missing from the dictionary.", missing.len());
+        issues.finish()?;
+    }
+
+    let duplicates = lexicon.sort_dedup();
+    info!("{} entries, {} duplicates, {} malformed lines.", lexicon.entries.len(), duplicates, lexicon.malformed.len());
+
+    if let Some(output) = settings.value_of("output")? {
+        let output = Path::new(&output);
+        if !settings.is_present("force")? && output.exists() {
+            return Err(Error::OutputExists { path: output.to_path_buf() });
+        }
+        lexicon.write(output)?;
+        info!("Wrote cleaned dictionary to {:?}.", output);
+    }
+    Ok(())
+}
+
+pub fn stats(settings: &Settings) -> Result<()> {
+    let stats = DatasetStats::from_output(Path::new(&settings.required("input")?))?;
+
+    println!("{:<20} {:>10}", "entries", stats.entries);
+    println!("{:<20} {:>10}", "words", stats.words);
+    println!("{:<20} {:>10}", "unique words", stats.unique_words);
+    println!("{:<20} {:>10}", "markers", stats.markers);
+    for (tag, count) in stats.marker_counts.iter() {
+        println!("  {:<18} {:>10}", tag, count);
+    }
+    println!("{:<20} {:>10}", "unresolved words", stats.unresolved_words);
+    println!("{:<20} {:>10}", "phonemes", stats.phonemes);
+    for (symbol, count) in stats.phoneme_counts.iter() {
+        println!("  {:<18} {:>10}", symbol, count);
+    }
+    Ok(())
+}
+
+pub fn validate(settings: &Settings) -> Result<()> {
+    let rules = pipeline::load_transcript_rules(&transcript_options(settings)?)?;
+    let resolvers = pipeline::build_resolvers(&resolver_options(settings)?, &rules.markers)?;
+    let mut issues = IssueLog::new(&issue_options(settings)?);
+    let entries = pipeline::collect_training_entries(&input_options(settings)?, &rules, &resolvers, &EntryOptions::default(), &mut issues)?;
+
+    let unresolved: usize = entries.iter()
+        .map(|v| v.phonemes.iter().filter(|ph| !ph.valid).count())
+        .sum();
+    info!("{} complete entries, {} unresolved words.", entries.len(), unresolved);
+    issues.finish()
+}
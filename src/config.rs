@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use toml::Value;
+use error::Error;
+use error::Result;
+
+/// A loaded `dict_walker.toml`: top-level keys, overridden by the keys of the selected `[profile.NAME]` table.
+///
+/// Every value remembers the full key it was read from (e.g. `profile.liepa.model`) so errors can point at it.
+#[derive(Debug, Default)]
+pub struct Config {
+    path: PathBuf,
+    values: BTreeMap<String, (String, Value)>
+}
+
+const PROFILE_TABLE: &str = "profile";
+
+impl Config {
+    pub fn load(path: &Path, profile: Option<&str>) -> Result<Config> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut config_str = String::new();
+        file.read_to_string(&mut config_str).map_err(|e| Error::io(path, e))?;
+        Config::parse(path, &config_str, profile)
+    }
+
+    pub fn parse(path: &Path, config_str: &str, profile: Option<&str>) -> Result<Config> {
+        let root = match config_str.parse::<Value>() {
+            Ok(Value::Table(v)) => v,
+            Ok(_) => return Err(Config::error(path, "", "the configuration must be a table")),
+            Err(e) => return Err(Config::error(path, "", e.to_string()))
+        };
+
+        let mut config = Config { path: path.to_path_buf(), values: BTreeMap::new() };
+        let mut profiles = None;
+        for (key, value) in root {
+            if key == PROFILE_TABLE {
+                profiles = Some(value);
+            } else {
+                config.values.insert(key.clone(), (key, value));
+            }
+        }
+
+        if let Some(name) = profile {
+            let key = format!("{}.{}", PROFILE_TABLE, name);
+            let table = match profiles.as_ref().and_then(|v| v.get(name)) {
+                Some(Value::Table(v)) => v,
+                Some(_) => return Err(Config::error(path, &key, "a profile must be a table")),
+                None => return Err(Config::error(path, &key, "no such profile"))
+            };
+            for (k, v) in table.iter() {
+                config.values.insert(k.clone(), (format!("{}.{}", key, k), v.clone()));
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn error(path: &Path, key: &str, message: impl Into<String>) -> Error {
+        Error::Config { path: path.to_path_buf(), key: key.to_string(), message: message.into() }
+    }
+
+    /// Builds an error about the value stored under `key`.
+    pub fn invalid(&self, key: &str, message: impl Into<String>) -> Error {
+        let full_key = match self.values.get(key) {
+            Some((v, _)) => v.as_str(),
+            None => key
+        };
+        Config::error(&self.path, full_key, message)
+    }
+
+    /// Fails on the first key not contained in `known`.
+    pub fn check_keys(&self, known: &[&str]) -> Result<()> {
+        match self.values.keys().find(|k| !known.contains(&k.as_str())) {
+            Some(k) => Err(self.invalid(k, "unknown key")),
+            None => Ok(())
+        }
+    }
+
+    /// String value of `key`, numbers are taken as their decimal notation.
+    pub fn get_str(&self, key: &str) -> Result<Option<String>> {
+        match self.values.get(key) {
+            Some((_, Value::String(v))) => Ok(Some(v.clone())),
+            Some((_, Value::Integer(v))) => Ok(Some(v.to_string())),
+            Some((_, Value::Float(v))) => Ok(Some(v.to_string())),
+            Some(_) => Err(self.invalid(key, "expected a string")),
+            None => Ok(None)
+        }
+    }
+
+    /// Like `get_str`, but relative paths are resolved against the directory holding the config file.
+    pub fn get_path(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get_str(key)?.map(|v| {
+            let path = Path::new(&v);
+            match self.path.parent() {
+                Some(dir) if path.is_relative() => dir.join(path).to_string_lossy().into_owned(),
+                _ => v
+            }
+        }))
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.values.get(key) {
+            Some((_, Value::Boolean(v))) => Ok(Some(*v)),
+            Some(_) => Err(self.invalid(key, "expected a boolean")),
+            None => Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn reads_numbers_as_strings() {
+        let config = Config::parse(Path::new("c.toml"), "max-words = 2\nmin-duration = 0.5\nforce = true\n", None).unwrap();
+        assert_eq!(config.get_str("max-words").unwrap(), Some(String::from("2")));
+        assert_eq!(config.get_str("min-duration").unwrap(), Some(String::from("0.5")));
+        assert!(config.get_str("force").is_err());
+        assert_eq!(config.get_bool("force").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn profile_wins_over_top_level() {
+        let config = Config::parse(Path::new("c.toml"), "max-words = 2\n[profile.liepa]\nmax-words = 3\n", Some("liepa")).unwrap();
+        assert_eq!(config.get_str("max-words").unwrap(), Some(String::from("3")));
+        assert!(Config::parse(Path::new("c.toml"), "", Some("other")).is_err());
+    }
+}
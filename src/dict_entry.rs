@@ -1,199 +1,466 @@
 use std::path::Path;
+use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::ffi::OsString;
 use std::fs::File;
-use std::error::Error;
+use std::io::Cursor;
 use std::io::Read;
 use decode::decode_utf16_le;
 use decode::decode_windows_1257;
 use decode::decode_utf16_be;
 use std::collections::HashMap;
-use std::fs::read_dir;
 use std::collections::HashSet;
+use std::str::FromStr;
+use wav::AudioInfo;
+use wav::WavHeader;
+use audio_quality::AnalysisOptions;
+use audio_quality::AudioQuality;
+use pairing::PairingPatterns;
+use traversal::TraversalOptions;
+use archive;
+use path_encoding;
+use issues::IssueKind;
+use issues::IssueLog;
+use error::Error;
+use error::Result;
 
-#[derive(Debug, Serialize)]
+/// Which audio and transcript files make up one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PairingMode {
+    /// Files with the same stem in the same directory.
+    #[default]
+    Directory,
+    /// Files with the same stem anywhere below the dictionary, so stems have to be unique across the whole tree.
+    Global
+}
+
+impl FromStr for PairingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        match s {
+            "directory" => Ok(PairingMode::Directory),
+            "global" => Ok(PairingMode::Global),
+            _ => Err(format!("\"{}\" is not a valid pairing mode, expected directory or global.", s))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct DictEntry {
+    /// Containing directory relative to the dictionary followed by the name, with `/` separators and
+    /// names that are not UTF-8 escaped (`path_encoding::escape`).
+    pub id: String,
+    /// File stem shared by audio and transcript, escaped like `id`.
     pub name: String,
     pub transcript: String,
-    pub containing_dir: String,
-    pub audio_path: String,
-    pub transcript_path: String
+    pub containing_dir: PathBuf,
+    pub audio_path: PathBuf,
+    pub transcript_path: PathBuf,
+    /// Speaker given by an input manifest, otherwise derived from `containing_dir`.
+    pub speaker: Option<String>,
+    /// Header data of the audio file, only filled when probed.
+    pub audio: Option<AudioInfo>,
+    /// Sample metrics of the audio file, only filled when analyzed.
+    pub quality: Option<AudioQuality>
 }
 
 impl DictEntry {
     pub fn new_empty() -> DictEntry {
         DictEntry {
+            id: String::new(),
             name: String::new(),
             transcript: String::new(),
-            containing_dir: String::new(),
-            audio_path: String::new(),
-            transcript_path: String::new()
+            containing_dir: PathBuf::new(),
+            audio_path: PathBuf::new(),
+            transcript_path: PathBuf::new(),
+            speaker: None,
+            audio: None,
+            quality: None
+        }
+    }
+
+    /// Reads the WAV header of the audio file into `audio`.
+    pub fn probe_audio(&mut self) -> Result<()> {
+        self.audio = Some(WavHeader::read(&self.audio_path)?.info());
+        Ok(())
+    }
+
+    /// Reads the WAV header into `audio` and decodes the samples to fill `quality`.
+    /// Sample formats the analysis does not support leave `quality` empty.
+    pub fn analyze_audio(&mut self, options: &AnalysisOptions) -> Result<()> {
+        let path = self.audio_path.as_path();
+        let header = WavHeader::read(path)?;
+        self.audio = Some(header.info());
+        self.quality = AudioQuality::analyze(path, &header, options)?;
+        if self.quality.is_none() {
+            warn!("Cannot analyze {}-bit {} samples of {:?}", header.bits_per_sample, header.codec(), path);
         }
+        Ok(())
+    }
+
+    /// `probe_audio` and, given analysis options, `analyze_audio` on the whole audio file already read into memory.
+    pub fn read_audio_bytes(&mut self, bytes: &[u8], analysis: Option<&AnalysisOptions>) -> Result<()> {
+        let header = WavHeader::parse(Cursor::new(bytes), bytes.len() as u64)
+            .map_err(|message| Error::AudioHeader { path: self.audio_path.clone(), message })?;
+        self.audio = Some(header.info());
+        if let Some(options) = analysis {
+            self.quality = AudioQuality::analyze_bytes(bytes, &header, options);
+            if self.quality.is_none() {
+                warn!("Cannot analyze {}-bit {} samples of {:?}", header.bits_per_sample, header.codec(), self.audio_path);
+            }
+        }
+        Ok(())
     }
 
     pub fn is_incomplete(&self) -> bool {
         self.name.is_empty() ||
             self.transcript.is_empty() ||
-            self.containing_dir.is_empty() ||
-            self.audio_path.is_empty() ||
+            self.containing_dir.as_os_str().is_empty() ||
+            self.audio_path.as_os_str().is_empty() ||
             self.transcript.is_empty()
     }
 
-    fn read_transcript(path: &Path) -> Result<String, String> {
-        let mut file = match File::open(path) {
-            Ok(v) => v,
-            Err(e) => return Err(String::from(e.description()))
-        };
+    pub fn read_transcript(path: &Path) -> Result<String> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
         let mut bytes: Vec<u8> = Vec::new();
-        match file.read_to_end(&mut bytes) {
-            Ok(_) => (),
-            Err(e) => return Err(String::from(e.description()))
-        };
+        file.read_to_end(&mut bytes).map_err(|e| Error::io(path, e))?;
+        DictEntry::decode_transcript(bytes, path)
+    }
 
+    /// Decodes transcript bytes as UTF-8, UTF-16 with a byte order mark or windows 1257, in that order.
+    /// `path` only names the transcript in messages.
+    pub fn decode_transcript(bytes: Vec<u8>, path: &Path) -> Result<String> {
         match String::from_utf8(bytes.clone()) {
             Ok(v) => Ok(v),
             Err(e) => {
                 debug!("Failed to read {:?} as UTF-8 ({}), checking for 0xFF 0xFE bytes...", path, e);
                 if bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] == 0xFE {
                     trace!("0xFF 0xFE bytes detected. Trying to decode as UTF-16LE...");
-                    match decode_utf16_le(&bytes[2..]) {
+                    match decode_utf16_le(&bytes[2..], path) {
                         Ok(v) => Ok(v),
                         Err(e) => {
                             debug!("Failed to read as UTF-16LE: {}", e);
                             trace!("Will try to read as windows 1257...");
-                            decode_windows_1257(&bytes)
+                            decode_windows_1257(&bytes, path)
                         }
                     }
                 } else if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
                     trace!("0xFE 0xFF bytes detected. Trying to decode as UTF-16BE...");
-                    match decode_utf16_be(&bytes[2..]) {
+                    match decode_utf16_be(&bytes[2..], path) {
                         Ok(v) => Ok(v),
                         Err(e) => {
                             debug!("Failed to read as UTF-16BE: {}", e);
                             trace!("Will try to read as windows 1257...");
-                            decode_windows_1257(&bytes)
+                            decode_windows_1257(&bytes, path)
                         }
                     }
                 } else {
                     debug!("No 0xFF 0xFE bytes.");
                     trace!("Will try to read as windows 1257...");
-                    decode_windows_1257(&bytes)
+                    decode_windows_1257(&bytes, path)
                 }
             }
         }
     }
 
-    // TODO: maybe use the .? syntax to propagate Err up
-    pub fn collect_entries(dir: &Path, audio_exts: &HashSet<String>, text_exts: &HashSet<String>) -> Result<Vec<DictEntry>, String> {
-        let mut paths: Vec<String> = Vec::new();
-        let mut files: Vec<String> = Vec::new();
-        let mut entries: HashMap<String, DictEntry> = HashMap::new();
+    /// Escaped directories of a path below `base`, or of an archive member below the archive root.
+    pub fn relative_components(base: &Path, path: &Path) -> Vec<String> {
+        match archive::split_address(path) {
+            Some((_, member)) => member.split('/')
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+                .collect(),
+            None => path.strip_prefix(base).unwrap_or(path)
+                .iter()
+                .map(path_encoding::escape)
+                .collect()
+        }
+    }
+
+    /// `id` of a paired entry: its containing directory relative to `base`, followed by the name.
+    pub fn relative_id(base: &Path, containing_dir: &Path, name: &str) -> String {
+        let mut components = DictEntry::relative_components(base, containing_dir);
+        components.push(String::from(name));
+        components.join("/")
+    }
+
+    /// Directory of `file` whose path relative to `base` is the `dir` captured by a pairing pattern, the parent
+    /// of `file` if the capture is no whole directory. Names are compared as `path_encoding::escape` writes them.
+    fn captured_dir(base: &Path, file: &Path, dir: &str) -> PathBuf {
+        file.ancestors()
+            .skip(1)
+            .take_while(|v| v.starts_with(base))
+            .find(|v| DictEntry::relative_components(base, v).join("/") == dir)
+            .or_else(|| file.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
 
-        let dir_str = match dir.to_str() {
-            Some(v) => String::from(v),
-            None => return Err(format!("Cannot get string representation of path \"{:?}\"", dir))
-        };
-        if dir.is_dir() {
-            paths.push(dir_str);
-        } else {
-            files.push(dir_str);
+    /// Containing directory and file name of a path or an archive member address.
+    fn split_parent(file: &Path) -> (PathBuf, OsString) {
+        match archive::split_address(file) {
+            Some((archive, member)) => match member.rfind('/') {
+                Some(idx) => (archive::address(&archive, &member[..idx]), path_encoding::unescape_bytes(&member[idx + 1..])),
+                None => (archive::address(&archive, ""), path_encoding::unescape_bytes(&member))
+            },
+            None => (
+                file.parent().map(Path::to_path_buf).unwrap_or_default(),
+                file.file_name().map(OsStr::to_os_string).unwrap_or_default()
+            )
         }
+    }
 
-        while !paths.is_empty() {
-            let p = paths.remove(0);
-            debug!("Visiting path \"{}\".", p);
+    /// Files below `dir` selected by `traversal`, see `TraversalOptions::list_files`.
+    /// Archives list their members, reading those `is_transcript` accepts.
+    fn list_files(dir: &Path, is_transcript: &dyn Fn(&str) -> bool, traversal: &TraversalOptions) -> Result<DictionaryFiles> {
+        if archive::is_archive(dir) {
+            let mut listing = DictionaryFiles { files: Vec::new(), contents: HashMap::new() };
+            archive::for_each_member(dir, |member, reader| {
+                if !traversal.selects_member(member) {
+                    trace!("Leaving out member {:?}.", member);
+                    return Ok(());
+                }
+                let address = archive::address(dir, member);
+                trace!("Adding member {:?}.", address);
+                if is_transcript(member) {
+                    listing.contents.insert(address.clone(), archive::read_member(dir, member, reader)?);
+                }
+                listing.files.push(address);
+                Ok(())
+            })?;
+            return Ok(listing);
+        }
+        Ok(DictionaryFiles { files: traversal.list_files(dir)?, contents: HashMap::new() })
+    }
 
-            let dir_entries = match read_dir(p) {
-                Ok(v) => v,
-                Err(e) => return Err(String::from(e.description()))
-            };
+    /// Records why an entry is incomplete.
+    pub fn record_incomplete(&self, issues: &mut IssueLog) {
+        match (self.audio_path.as_os_str().is_empty(), self.transcript_path.as_os_str().is_empty()) {
+            (false, true) => issues.record(IssueKind::OrphanAudio, &self.audio_path, String::from("has no transcript")),
+            (true, false) => issues.record(IssueKind::OrphanTranscript, &self.transcript_path, String::from("has no audio")),
+            _ => issues.record(IssueKind::EmptyTranscript, &self.transcript_path, String::from("holds no text"))
+        }
+    }
 
-            for entry in dir_entries {
-                let entry = match entry {
-                    Ok(v) => v,
-                    Err(e) => return Err(String::from(e.description()))
-                };
-
-                let path_str = match entry.path().to_str() {
-                    Some(v) => String::from(v),
-                    None => return Err(format!("Cannot get string representation of path \"{:?}\"", entry.path()))
-                };
-                if entry.path().is_dir() {
-                    trace!("Adding path \"{}\".", path_str);
-                    paths.push(String::from(path_str));
-                } else {
-                    trace!("Adding file \"{}\".", path_str);
-                    files.push(String::from(path_str));
+    /// Drops incomplete entries and assigns the IDs of the rest. `dir` is the walked dictionary, `undecodable`
+    /// the keys of entries whose transcript was already reported.
+    fn complete_entries<K>(dir: &Path, mut entries: HashMap<K, DictEntry>, undecodable: &HashSet<K>, issues: &mut IssueLog) -> Vec<DictEntry>
+        where K: std::hash::Hash + Eq {
+        let base = if dir.is_dir() { dir } else { dir.parent().unwrap_or(dir) };
+        entries
+            .drain()
+            .filter(|kv| {
+                let incomplete = kv.1.is_incomplete();
+                if incomplete && !undecodable.contains(&kv.0) {
+                    kv.1.record_incomplete(issues);
+                }
+                !incomplete
+            })
+            .map(|kv| {
+                let mut entry = kv.1;
+                entry.id = DictEntry::relative_id(base, &entry.containing_dir, &entry.name);
+                entry
+            })
+            .collect()
+    }
+
+    /// Sets the audio or transcript of an entry. A second file of the same kind is a collision, which keeps the
+    /// first file in keep going mode; so does a transcript that cannot be decoded, which leaves the entry without one.
+    /// Returns whether the file was taken.
+    fn add_file(&mut self, file: &Path, is_audio: bool, listing: &mut DictionaryFiles, issues: &mut IssueLog) -> Result<bool> {
+        let existing = if is_audio { &self.audio_path } else { &self.transcript_path };
+        if !existing.as_os_str().is_empty() {
+            let error = Error::NamingCollision { first: existing.clone(), second: file.to_path_buf() };
+            issues.report(IssueKind::Collision, file, error)?;
+            return Ok(false);
+        }
+
+        if is_audio {
+            self.audio_path = file.to_path_buf();
+        } else {
+            match listing.read_transcript(file) {
+                Ok(v) => {
+                    self.transcript_path = file.to_path_buf();
+                    self.transcript = v;
+                },
+                Err(e) => {
+                    issues.report(IssueKind::DecodeFailure, file, e)?;
+                    return Ok(false);
                 }
             }
         }
+        Ok(true)
+    }
 
-        for file_str in files {
-            let file = Path::new(&file_str);
+    pub fn collect_entries(dir: &Path, audio_exts: &HashSet<String>, text_exts: &HashSet<String>, pairing: PairingMode, traversal: &TraversalOptions, issues: &mut IssueLog) -> Result<Vec<DictEntry>> {
+        // keyed by containing directory (empty in global mode) and file stem, compared as they are on disk
+        let mut entries: HashMap<(PathBuf, OsString), DictEntry> = HashMap::new();
+        let mut undecodable: HashSet<(PathBuf, OsString)> = HashSet::new();
+        let has_text_ext = |member: &str| Path::new(member).extension()
+            .map(|v| text_exts.contains(&v.to_string_lossy().to_lowercase()))
+            .unwrap_or(false);
+        let mut listing = DictEntry::list_files(dir, &has_text_ext, traversal)?;
 
-            let file_stem = match file.file_stem() {
-                Some(v) => match v.to_str() {
-                    Some(v) => v,
-                    None => return Err(format!("Cannot get &str from OsStr \"{:?}\"!", v))
-                },
-                None => return Err(format!("Cannot get file stem from {:?}!", file))
+        for file in std::mem::take(&mut listing.files) {
+            let (containing_dir, file_name) = DictEntry::split_parent(&file);
+            let file_name = Path::new(&file_name);
+            let file_stem = file_name.file_stem().unwrap_or_default();
+            let extension = file_name.extension()
+                .map(|v| v.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            let is_audio = if audio_exts.contains(&extension) {
+                true
+            } else if text_exts.contains(&extension) {
+                false
+            } else {
+                issues.record(IssueKind::UnknownExtension, &file, format!("has the unknown extension \"{}\"", extension));
+                continue;
             };
 
-            let mut remove: Option<String> = None;
-
-            {
-                let mut entry = entries.entry(String::from(file_stem)).or_insert(DictEntry::new_empty());
-                let extension = match file.extension() {
-                    Some(v) => match v.to_str() {
-                        Some(v) => v,
-                        None => return Err(format!("Cannot get &str from OsStr \"{:?}\"!", v))
-                    },
-                    None => ""
-                };
-
-                entry.name = String::from(file_stem);
-                entry.containing_dir = match file.parent() {
-                    Some(v) => match v.to_str() {
-                        Some(v) => String::from(v),
-                        None => return Err(format!("Cannot get &str from Path \"{:?}\"!", v))
-                    },
-                    None => return Err(format!("Cannot resolve containing directory for {:?}!", file))
-                };
-
-                if audio_exts.contains(&extension.to_lowercase()) {
-                    if !entry.audio_path.is_empty() {
-                        return Err(format!("Naming collision: \"{}\" vs \"{}\"!", entry.audio_path, file_str));
-                    }
-                    entry.audio_path = String::from(file_str.clone());
-                } else if text_exts.contains(&extension.to_lowercase()) {
-                    if !entry.transcript_path.is_empty() {
-                        return Err(format!("Naming collision: \"{}\" vs \"{}\"!", entry.transcript_path, file_str));
-                    }
-                    entry.transcript_path = String::from(file_str.clone());
-                    entry.transcript = DictEntry::read_transcript(file)?;
-                } else {
-                    warn!("Unknown file extension \"{}\", file {:?}!", extension, file);
-                    remove = Some(String::from(file_stem));
-                }
+            let key = match pairing {
+                PairingMode::Directory => (containing_dir.clone(), file_stem.to_os_string()),
+                PairingMode::Global => (PathBuf::new(), file_stem.to_os_string())
+            };
+            let entry = entries.entry(key.clone()).or_insert_with(DictEntry::new_empty);
+            entry.name = path_encoding::escape(file_stem);
+            if entry.containing_dir.as_os_str().is_empty() {
+                entry.containing_dir = containing_dir.clone();
+            }
+            if is_audio && entry.audio_path.as_os_str().is_empty() {
+                // in global mode the transcript may lie elsewhere, the audio decides
+                entry.containing_dir = containing_dir;
+            }
+            if !entry.add_file(&file, is_audio, &mut listing, issues)? && entry.transcript_path.as_os_str().is_empty() && !is_audio {
+                undecodable.insert(key);
             }
+        }
+
+        Ok(DictEntry::complete_entries(dir, entries, &undecodable, issues))
+    }
 
-            match remove {
-                Some(v) => { entries.remove(&v); },
-                None => ()
+    /// Pairs the files below `dir` by `patterns` instead of stems and extensions. The `dir` part of the
+    /// patterns becomes the containing directory of the entry and `id` its name.
+    pub fn collect_by_patterns(dir: &Path, patterns: &PairingPatterns, traversal: &TraversalOptions, issues: &mut IssueLog) -> Result<Vec<DictEntry>> {
+        let base = if dir.is_dir() { dir } else { dir.parent().unwrap_or(dir) };
+        let mut entries: HashMap<(String, String), DictEntry> = HashMap::new();
+        let mut undecodable: HashSet<(String, String)> = HashSet::new();
+        let is_text = |member: &str| patterns.audio.captures(member).is_none() && patterns.text.captures(member).is_some();
+        let mut listing = DictEntry::list_files(dir, &is_text, traversal)?;
+
+        for file in std::mem::take(&mut listing.files) {
+            let relative = DictEntry::relative_components(base, &file).join("/");
+
+            let (key, is_audio) = match (patterns.audio.captures(&relative), patterns.text.captures(&relative)) {
+                (Some(key), _) => (key, true),
+                (None, Some(key)) => (key, false),
+                (None, None) => {
+                    issues.record(IssueKind::UnmatchedFile, &file, String::from("matches no pairing pattern"));
+                    continue;
+                }
             };
+
+            let entry = entries.entry(key.clone()).or_insert_with(DictEntry::new_empty);
+            entry.name = key.1.clone();
+            entry.containing_dir = if archive::is_archive(dir) {
+                archive::address(dir, &key.0)
+            } else {
+                DictEntry::captured_dir(base, &file, &key.0)
+            };
+            if !entry.add_file(&file, is_audio, &mut listing, issues)? && entry.transcript_path.as_os_str().is_empty() && !is_audio {
+                undecodable.insert(key);
+            }
         }
 
-        let values = entries
-            .drain()
-            .filter(|kv| {
-                let incomplete = kv.1.is_incomplete();
-                if incomplete {
-                    warn!("Incomplete entry: {:#?}", kv.1);
-                }
-                !incomplete
-            })
-            .map(|kv| kv.1)
-            .collect();
-        Ok(values)
+        Ok(DictEntry::complete_entries(dir, entries, &undecodable, issues))
+    }
+}
+
+/// Files of a dictionary: paths on disk, or addresses of archive members along with the transcripts
+/// read while listing the archive.
+struct DictionaryFiles {
+    files: Vec<PathBuf>,
+    contents: HashMap<PathBuf, Vec<u8>>
+}
+
+impl DictionaryFiles {
+    fn read_transcript(&mut self, file: &Path) -> Result<String> {
+        match self.contents.remove(file) {
+            Some(bytes) => DictEntry::decode_transcript(bytes, file),
+            None => DictEntry::read_transcript(file)
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use issues::IssueOptions;
+
+    /// A fresh directory holding `files`, each with a short transcript.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dict_walker_dict_entry_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files.iter() {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"labas").unwrap();
+        }
+        dir
+    }
+
+    fn patterns(audio: &str, text: &str) -> PairingPatterns {
+        PairingPatterns { audio: audio.parse().unwrap(), text: text.parse().unwrap() }
+    }
 
+    #[test]
+    fn takes_whole_captured_directories() {
+        let base = Path::new("/corpus");
+        assert_eq!(DictEntry::captured_dir(base, &base.join("spk1/wav/a.wav"), "spk1"), base.join("spk1"));
+        assert_eq!(DictEntry::captured_dir(base, &base.join("spk1/day2/wav/a.wav"), "spk1/day2"), base.join("spk1/day2"));
+        assert_eq!(DictEntry::captured_dir(base, &base.join("wav/a.wav"), ""), base.to_path_buf());
+    }
+
+    #[test]
+    fn falls_back_to_the_parent_of_partial_captures() {
+        let base = Path::new("/corpus");
+        assert_eq!(DictEntry::captured_dir(base, &base.join("spk1_audio/a.wav"), "spk1"), base.join("spk1_audio"));
+        // a directory of the same name above the dictionary is not taken either
+        assert_eq!(DictEntry::captured_dir(base, &base.join("wav/a.wav"), "corpus"), base.join("wav"));
+    }
+
+    #[test]
+    fn pairs_files_by_patterns() {
+        let dir = tree("patterns", &["spk1/wav/a.wav", "spk1/txt/a.txt", "spk2/wav/b.wav", "spk1/notes.md"]);
+        let mut issues = IssueLog::new(&IssueOptions::default());
+        let patterns = patterns("{dir}/wav/{id}.wav", "{dir}/txt/{id}.txt");
+        let entries = DictEntry::collect_by_patterns(&dir, &patterns, &TraversalOptions::default(), &mut issues).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, "spk1/a");
+        assert_eq!(entries[0].name, "a");
+        assert_eq!(entries[0].containing_dir, dir.join("spk1"));
+        assert_eq!(entries[0].audio_path, dir.join("spk1/wav/a.wav"));
+        assert_eq!(entries[0].transcript, "labas");
+
+        let mut kinds: Vec<IssueKind> = issues.issues().iter().map(|v| v.kind).collect();
+        kinds.sort();
+        assert_eq!(kinds, vec![IssueKind::OrphanAudio, IssueKind::UnmatchedFile]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pairs_partial_directory_captures() {
+        let dir = tree("partial", &["spk1_audio/a.wav", "spk1_text/a.txt"]);
+        let mut issues = IssueLog::new(&IssueOptions::default());
+        let patterns = patterns("re:^(?P<dir>[^/]+)_audio/(?P<id>[^/]+)\\.wav$", "re:^(?P<dir>[^/]+)_text/(?P<id>[^/]+)\\.txt$");
+        let entries = DictEntry::collect_by_patterns(&dir, &patterns, &TraversalOptions::default(), &mut issues).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a");
+        assert!(entries[0].containing_dir == dir.join("spk1_audio") || entries[0].containing_dir == dir.join("spk1_text"));
+        assert!(issues.issues().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    Io { path: PathBuf, source: io::Error },
+    Decode { path: PathBuf, message: String },
+    NonUnicodePath { path: PathBuf },
+    NamingCollision { first: PathBuf, second: PathBuf },
+    ModelLoad { path: PathBuf, message: String },
+    Serialization { path: PathBuf, source: serde_json::Error },
+    OutputExists { path: PathBuf },
+    Config { path: PathBuf, key: String, message: String },
+    MissingOption { key: String },
+    RuleFile { path: PathBuf, message: String },
+    UnresolvedWord { path: PathBuf, word: String },
+    AudioHeader { path: PathBuf, message: String },
+    Manifest { path: PathBuf, line: usize, message: String },
+    Archive { path: PathBuf, message: String }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io { path: path.into(), source }
+    }
+
+    pub fn decode(path: impl Into<PathBuf>, message: impl Into<String>) -> Error {
+        Error::Decode { path: path.into(), message: message.into() }
+    }
+
+    pub fn model_load(path: impl Into<PathBuf>, message: impl Into<String>) -> Error {
+        Error::ModelLoad { path: path.into(), message: message.into() }
+    }
+
+    pub fn rule_file(path: impl Into<PathBuf>, message: impl Into<String>) -> Error {
+        Error::RuleFile { path: path.into(), message: message.into() }
+    }
+
+    /// Process exit code the command line tool uses for this kind of failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 2,
+            Error::Decode { .. } => 3,
+            Error::NonUnicodePath { .. } => 4,
+            Error::NamingCollision { .. } => 5,
+            Error::ModelLoad { .. } => 6,
+            Error::Serialization { .. } => 7,
+            Error::OutputExists { .. } => 8,
+            Error::Config { .. } => 9,
+            Error::MissingOption { .. } => 10,
+            Error::RuleFile { .. } => 11,
+            Error::UnresolvedWord { .. } => 12,
+            Error::AudioHeader { .. } => 13,
+            Error::Manifest { .. } => 14,
+            Error::Archive { .. } => 15
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } =>
+                write!(f, "I/O error on {:?}: {}", path, source),
+            Error::Decode { path, message } =>
+                write!(f, "Failed to decode {:?}: {}", path, message),
+            Error::NonUnicodePath { path } =>
+                write!(f, "Cannot get string representation of path {:?}", path),
+            Error::NamingCollision { first, second } =>
+                write!(f, "Naming collision: {:?} vs {:?}", first, second),
+            Error::ModelLoad { path, message } =>
+                write!(f, "Failed to load model from {:?}: {}", path, message),
+            Error::Serialization { path, source } =>
+                write!(f, "Cannot serialize processed entries to {:?}: {}", path, source),
+            Error::OutputExists { path } =>
+                write!(f, "Output file {:?} already exists! Use the --force (or -f) to force overwriting of the output file.", path),
+            Error::Config { path, key, message } =>
+                write!(f, "Invalid configuration {:?}, key \"{}\": {}", path, key, message),
+            Error::MissingOption { key } =>
+                write!(f, "Option --{} must be given on the command line or in the configuration file", key),
+            Error::RuleFile { path, message } =>
+                write!(f, "Invalid rule file {:?}: {}", path, message),
+            Error::UnresolvedWord { path, word } =>
+                write!(f, "No resolver knows the word \"{}\" of {:?}", word, path),
+            Error::AudioHeader { path, message } =>
+                write!(f, "Malformed WAV header in {:?}: {}", path, message),
+            Error::Manifest { path, line, message } =>
+                write!(f, "Invalid manifest {:?}, line {}: {}", path, line, message),
+            Error::Archive { path, message } =>
+                write!(f, "Cannot read archive {:?}: {}", path, message)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Serialization { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
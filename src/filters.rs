@@ -0,0 +1,110 @@
+use dict_entry::DictEntry;
+use training_entry::TrainingEntry;
+
+/// Selection rules applied to every entry before it is written. Unset rules let everything through.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilters {
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub mono: bool,
+    pub min_words: Option<usize>,
+    pub max_words: Option<usize>,
+    /// Upper bound of phonemes per second of audio, catches transcripts that do not match the recording.
+    pub max_phoneme_rate: Option<f64>,
+    /// Rejection thresholds on `AudioQuality`.
+    pub max_clipping_percent: Option<f64>,
+    pub max_dc_offset: Option<f64>,
+    pub min_rms_dbfs: Option<f64>,
+    pub max_silence_ratio: Option<f64>
+}
+
+impl EntryFilters {
+    /// Whether some rule needs the WAV header of the entries (`DictEntry::probe_audio`).
+    pub fn needs_audio(&self) -> bool {
+        self.min_duration.is_some() ||
+            self.max_duration.is_some() ||
+            self.sample_rate.is_some() ||
+            self.mono ||
+            self.max_phoneme_rate.is_some()
+    }
+
+    /// Whether some rule needs the sample metrics of the entries (`DictEntry::analyze_audio`).
+    pub fn needs_quality(&self) -> bool {
+        self.max_clipping_percent.is_some() ||
+            self.max_dc_offset.is_some() ||
+            self.min_rms_dbfs.is_some() ||
+            self.max_silence_ratio.is_some()
+    }
+
+    /// Checks the rules on the audio of a probed entry, returning why it has to be dropped.
+    pub fn check_audio(&self, entry: &DictEntry) -> Option<String> {
+        let audio = match entry.audio {
+            Some(ref v) => v,
+            None => return None
+        };
+
+        match self.min_duration {
+            Some(v) if audio.duration_seconds < v =>
+                return Some(format!("duration {:.2}s is below {}s", audio.duration_seconds, v)),
+            _ => ()
+        }
+        match self.max_duration {
+            Some(v) if audio.duration_seconds > v =>
+                return Some(format!("duration {:.2}s is above {}s", audio.duration_seconds, v)),
+            _ => ()
+        }
+        match self.sample_rate {
+            Some(v) if audio.sample_rate != v =>
+                return Some(format!("sample rate is {} Hz instead of {} Hz", audio.sample_rate, v)),
+            _ => ()
+        }
+        if self.mono && audio.channels != 1 {
+            return Some(format!("{} channels instead of mono", audio.channels));
+        }
+
+        let quality = match entry.quality {
+            Some(ref v) => v,
+            None => return None
+        };
+        match self.max_clipping_percent {
+            Some(v) if quality.clipping_percent > v =>
+                return Some(format!("{:.2}% of the samples clip, more than {}%", quality.clipping_percent, v)),
+            _ => ()
+        }
+        match self.max_dc_offset {
+            Some(v) if quality.dc_offset.abs() > v =>
+                return Some(format!("DC offset {:.4} exceeds {}", quality.dc_offset, v)),
+            _ => ()
+        }
+        match self.min_rms_dbfs {
+            Some(v) if quality.rms_dbfs < v =>
+                return Some(format!("RMS level {:.1} dBFS is below {} dBFS", quality.rms_dbfs, v)),
+            _ => ()
+        }
+        match self.max_silence_ratio {
+            Some(v) if quality.silence_ratio > v =>
+                Some(format!("{:.0}% silence, more than {:.0}%", 100.0 * quality.silence_ratio, 100.0 * v)),
+            _ => None
+        }
+    }
+
+    /// Checks the rules on the converted transcript, returning why the entry has to be dropped.
+    pub fn check_entry(&self, entry: &TrainingEntry) -> Option<String> {
+        match self.min_words {
+            Some(v) if entry.word_count < v =>
+                return Some(format!("{} words, fewer than {}", entry.word_count, v)),
+            _ => ()
+        }
+        match self.max_words {
+            Some(v) if entry.word_count > v =>
+                return Some(format!("{} words, more than {}", entry.word_count, v)),
+            _ => ()
+        }
+        match (self.max_phoneme_rate, entry.phoneme_rate()) {
+            (Some(v), Some(rate)) if rate > v =>
+                Some(format!("{:.1} phonemes per second, more than {}", rate, v)),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use path_encoding::PathEncoding;
+use error::Error;
+use error::Result;
+
+/// What is wrong with a file of the dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// Audio without a transcript.
+    OrphanAudio,
+    /// Transcript without audio.
+    OrphanTranscript,
+    /// Transcript without any text.
+    EmptyTranscript,
+    /// Second audio or transcript file of the same entry.
+    Collision,
+    /// Transcript that cannot be read or decoded.
+    DecodeFailure,
+    /// File with neither an audio nor a text extension.
+    UnknownExtension,
+    /// File matching neither pairing pattern.
+    UnmatchedFile,
+    /// Audio with a malformed header.
+    DefectiveAudio,
+    /// Word no resolver knows, or only the dead end.
+    UnresolvedWord
+}
+
+impl IssueKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            IssueKind::OrphanAudio => "orphan audio",
+            IssueKind::OrphanTranscript => "orphan transcript",
+            IssueKind::EmptyTranscript => "empty transcript",
+            IssueKind::Collision => "collision",
+            IssueKind::DecodeFailure => "decode failure",
+            IssueKind::UnknownExtension => "unknown extension",
+            IssueKind::UnmatchedFile => "unmatched file",
+            IssueKind::DefectiveAudio => "defective audio",
+            IssueKind::UnresolvedWord => "unresolved word"
+        }
+    }
+}
+
+/// One problem found in the dictionary, a line of the issue report.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub path: String,
+    pub message: String
+}
+
+/// How problems of the dictionary are handled.
+#[derive(Debug, Clone, Default)]
+pub struct IssueOptions {
+    /// Record collisions, decode failures and unresolved words and skip the affected file or entry
+    /// instead of aborting.
+    pub keep_going: bool,
+    /// Where to write the issues as JSON lines.
+    pub report: Option<PathBuf>,
+    /// How the report writes paths that are not UTF-8.
+    pub path_encoding: PathEncoding
+}
+
+/// Problems found during a walk.
+#[derive(Debug, Default)]
+pub struct IssueLog {
+    options: IssueOptions,
+    issues: Vec<Issue>
+}
+
+impl IssueLog {
+    pub fn new(options: &IssueOptions) -> IssueLog {
+        IssueLog { options: options.clone(), issues: Vec::new() }
+    }
+
+    /// Records a problem the walk always gets past, such as an orphan or a file of unknown type.
+    pub fn record(&mut self, kind: IssueKind, path: &Path, message: String) {
+        warn!("{}: {:?} {}", kind.name(), path, message);
+        self.issues.push(Issue { kind, path: self.options.path_encoding.encode(path), message });
+    }
+
+    /// Records an error in keep going mode, so the caller can skip the affected file, or returns it otherwise.
+    pub fn report(&mut self, kind: IssueKind, path: &Path, error: Error) -> Result<()> {
+        if self.options.keep_going {
+            self.record(kind, path, error.to_string());
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Writes the report, if one was asked for, and logs the number of issues of every kind.
+    pub fn finish(&self) -> Result<()> {
+        if let Some(ref path) = self.options.report {
+            write_report(&self.issues, path)?;
+            info!("Wrote {} issues to {:?}.", self.issues.len(), path);
+        }
+        if self.issues.is_empty() {
+            return Ok(());
+        }
+
+        let mut counts: BTreeMap<IssueKind, usize> = BTreeMap::new();
+        for issue in self.issues.iter() {
+            *counts.entry(issue.kind).or_insert(0) += 1;
+        }
+        info!("{:<20} {:>8}", "issue", "count");
+        for (kind, count) in counts.iter() {
+            info!("{:<20} {:>8}", kind.name(), count);
+        }
+        info!("{:<20} {:>8}", "total", self.issues.len());
+        Ok(())
+    }
+}
+
+/// Writes one JSON object per issue and line.
+pub fn write_report(issues: &[Issue], path: &Path) -> Result<()> {
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut output = BufWriter::new(file);
+    for issue in issues {
+        serde_json::to_writer(&mut output, issue)
+            .map_err(|e| Error::Serialization { path: path.to_path_buf(), source: e })?;
+        output.write_all(b"\n").map_err(|e| Error::io(path, e))?;
+    }
+    output.flush().map_err(|e| Error::io(path, e))
+}
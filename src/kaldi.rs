@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::path::PathBuf;
+use dict_entry::DictEntry;
+use training_entry::TrainingEntry;
+use phonemes::Phoneme;
+use phonemes::LAST_PHONETIC_ORDINAL;
+use markers::MarkerTable;
+use writers::DatasetWriter;
+use writers::DataDirOptions;
+use writers::Speakers;
+use writers::identifier;
+use writers::write_lines;
+use writers::write_spk2utt;
+use error::Error;
+use error::Result;
+
+struct Utterance {
+    speaker: String,
+    audio_path: String,
+    words: Vec<String>
+}
+
+/// Collects converted entries and writes them as a Kaldi data directory.
+pub struct KaldiWriter {
+    dir: PathBuf,
+    speakers: Speakers,
+    markers: MarkerTable,
+    utterances: BTreeMap<String, Utterance>,
+    lexicon: BTreeSet<(String, String)>,
+    phones: BTreeMap<i8, BTreeSet<String>>,
+    unpronounceable: BTreeSet<String>
+}
+
+/// Kaldi phone name of a phoneme: the bare symbol, stressed vowels get a `1` suffix.
+fn phone(ph: &Phoneme) -> String {
+    if ph.accented {
+        format!("{}1", ph.symbol)
+    } else {
+        ph.symbol.clone()
+    }
+}
+
+impl KaldiWriter {
+    /// `root` is the walked dictionary, the speaker directory level is counted from it.
+    pub fn new(dir: &Path, root: &Path, options: &DataDirOptions, markers: &MarkerTable) -> KaldiWriter {
+        KaldiWriter {
+            dir: dir.to_path_buf(),
+            speakers: Speakers::new(root, options),
+            markers: markers.clone(),
+            utterances: BTreeMap::new(),
+            lexicon: BTreeSet::new(),
+            phones: BTreeMap::new(),
+            unpronounceable: BTreeSet::new()
+        }
+    }
+}
+
+impl DatasetWriter for KaldiWriter {
+    /// `entry` must carry its word records (`EntryOptions::provenance`).
+    fn add(&mut self, source: &DictEntry, entry: &TrainingEntry) -> Result<()> {
+        let (speaker, id) = self.speakers.utterance(source);
+        if let Some(other) = self.utterances.get(&id) {
+            return Err(Error::NamingCollision {
+                first: PathBuf::from(&other.audio_path),
+                second: source.audio_path.clone()
+            });
+        }
+
+        let mut words = Vec::new();
+        for record in entry.words.iter().flat_map(|v| v.iter()) {
+            let word = identifier(&record.word);
+            let valid: Vec<&Vec<Phoneme>> = record.variants.iter()
+                .filter(|v| v.iter().all(|ph| ph.valid))
+                .collect();
+            if valid.is_empty() {
+                self.unpronounceable.insert(word.clone());
+            }
+            for variant in valid {
+                let phones: Vec<String> = variant.iter().map(phone).collect();
+                for ph in variant.iter() {
+                    self.phones.entry(ph.ordinal).or_default().insert(phone(ph));
+                }
+                self.lexicon.insert((word.clone(), phones.join(" ")));
+            }
+            words.push(word);
+        }
+
+        self.utterances.insert(id, Utterance { speaker, audio_path: entry.audio_path.clone(), words });
+        Ok(())
+    }
+
+    /// Writes `wav.scp`, `text`, `utt2spk` and `spk2utt` along with the `data/local/dict` files `lexicon.txt`,
+    /// `silence_phones.txt`, `nonsilence_phones.txt` and `optional_silence.txt`. Words resolved to `ERR-`
+    /// phonemes only stay in `text` but get no `lexicon.txt` entry, they are reported as warnings.
+    fn finish(&mut self) -> Result<usize> {
+        create_dir_all(&self.dir).map_err(|e| Error::io(&self.dir, e))?;
+
+        write_lines(&self.dir, "wav.scp", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.audio_path)))?;
+        write_lines(&self.dir, "text", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.words.join(" "))))?;
+        write_lines(&self.dir, "utt2spk", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.speaker)))?;
+        write_spk2utt(&self.dir, self.utterances.iter().map(|(id, v)| (id.as_str(), v.speaker.as_str())))?;
+
+        for word in self.unpronounceable.iter() {
+            warn!("Word \"{}\" has no valid pronunciation, left out of lexicon.txt", word);
+        }
+        write_lines(&self.dir, "lexicon.txt", self.lexicon.iter()
+            .map(|(word, phones)| format!("{} {}", word, phones)))?;
+
+        // markers come after the phonetic inventory, see phonemes::LAST_PHONETIC_ORDINAL
+        write_lines(&self.dir, "nonsilence_phones.txt", self.phones.iter()
+            .filter(|&(ordinal, _)| *ordinal <= LAST_PHONETIC_ORDINAL)
+            .map(|(_, phones)| phones.iter().cloned().collect::<Vec<String>>().join(" ")))?;
+        write_lines(&self.dir, "silence_phones.txt", self.markers.markers().iter()
+            .filter(|v| v.ordinal > LAST_PHONETIC_ORDINAL)
+            .map(|v| phone(&v.phoneme())))?;
+        write_lines(&self.dir, "optional_silence.txt", vec![phone(&self.markers.pause())])?;
+        Ok(self.utterances.len())
+    }
+}
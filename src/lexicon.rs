@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use regex::Regex;
+use phonemes::Phoneme;
+use markers::MarkerTable;
+use error::Error;
+use error::Result;
+
+/// One `word SYMBOL SYMBOL ...` line of a grapheme-phoneme dictionary.
+/// Alternative pronunciations are either repeated lines or CMUdict style `word(2)` lines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LexiconEntry {
+    pub word: String,
+    /// The `(N)` variant index, if written.
+    pub index: Option<u32>,
+    pub symbols: Vec<String>
+}
+
+impl LexiconEntry {
+    pub fn phonemes(&self, markers: &MarkerTable) -> Vec<Phoneme> {
+        self.symbols.iter()
+            .map(|v| Phoneme::from_symbol_in(v, false, markers))
+            .collect()
+    }
+
+    /// Symbols which are neither part of the phoneme inventory nor marker tags.
+    pub fn invalid_symbols(&self, markers: &MarkerTable) -> Vec<&str> {
+        self.symbols.iter()
+            .filter(|v| !Phoneme::from_symbol_in(v, false, markers).valid)
+            .map(|v| v.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Lexicon {
+    pub entries: Vec<LexiconEntry>,
+    /// Line number (1-based) and content of every line that could not be parsed.
+    pub malformed: Vec<(usize, String)>
+}
+
+impl Lexicon {
+    pub fn load(path: &Path) -> Result<Lexicon> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut dict_str = String::new();
+        file.read_to_string(&mut dict_str).map_err(|e| Error::io(path, e))?;
+        Ok(Lexicon::parse(&dict_str))
+    }
+
+    pub fn parse(dict_str: &str) -> Lexicon {
+        lazy_static! {
+            static ref ENTRY_RE: Regex = Regex::new(r"^(?P<word>[^ ]+?)(?:\((?P<index>[0-9]+)\))? +(?P<symbols>.+)$").unwrap();
+        }
+
+        let mut lexicon = Lexicon::default();
+        for (idx, line) in dict_str.lines().enumerate() {
+            // an index too large for a u32 makes the line malformed as well
+            let entry = ENTRY_RE.captures(line).and_then(|caps| {
+                let index = match caps.name("index") {
+                    Some(v) => Some(v.as_str().parse::<u32>().ok()?),
+                    None => None
+                };
+                Some(LexiconEntry {
+                    word: caps["word"].to_string(),
+                    index,
+                    symbols: caps["symbols"].split_whitespace().map(String::from).collect()
+                })
+            });
+            match entry {
+                Some(v) => lexicon.entries.push(v),
+                None => lexicon.malformed.push((idx + 1, line.to_string()))
+            }
+        }
+        lexicon
+    }
+
+    /// Every distinct pronunciation of every word, in variant order: indexed entries by their index
+    /// (a plain entry counts as `(1)`), repeated entries in file order.
+    pub fn variants(&self) -> HashMap<&str, Vec<&LexiconEntry>> {
+        let mut variants: HashMap<&str, Vec<&LexiconEntry>> = HashMap::new();
+        for entry in self.entries.iter() {
+            let list = variants.entry(entry.word.as_str()).or_default();
+            if !list.iter().any(|v| v.symbols == entry.symbols) {
+                list.push(entry);
+            }
+        }
+        for list in variants.values_mut() {
+            list.sort_by_key(|v| v.index.unwrap_or(1));
+        }
+        variants
+    }
+
+    /// Sorts entries by word and drops exact duplicates. The entries of a word keep their file order, which
+    /// `variants` goes by. Returns the number of dropped entries.
+    pub fn sort_dedup(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.sort_by(|a, b| a.word.cmp(&b.word));
+        let mut seen: HashSet<LexiconEntry> = HashSet::new();
+        self.entries.retain(|v| seen.insert(v.clone()));
+        before - self.entries.len()
+    }
+
+    pub fn words(&self) -> HashSet<&str> {
+        self.entries.iter().map(|v| v.word.as_str()).collect()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path).map_err(|e| Error::io(path, e))?;
+        for entry in self.entries.iter() {
+            let word = match entry.index {
+                Some(index) => format!("{}({})", entry.word, index),
+                None => entry.word.clone()
+            };
+            writeln!(file, "{} {}", word, entry.symbols.join(" "))
+                .map_err(|e| Error::io(path, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_variant_indices() {
+        let lexicon = Lexicon::parse("labas L A B A S\nlabas(2) L O B A S\nword(99999999999) A B\nbad\n");
+        assert_eq!(lexicon.entries.len(), 2);
+        assert_eq!(lexicon.entries[1].index, Some(2));
+        assert_eq!(lexicon.malformed, vec![(3, String::from("word(99999999999) A B")), (4, String::from("bad"))]);
+    }
+
+    #[test]
+    fn keeps_the_variant_order_when_deduplicating() {
+        let mut lexicon = Lexicon::parse("rytas R I_ T A S\nlabas L A B A S\nlabas A B\nlabas L A B A S\ngera G E R A\n");
+        assert_eq!(lexicon.sort_dedup(), 1);
+        let order: Vec<String> = lexicon.entries.iter().map(|v| format!("{} {}", v.word, v.symbols.join(" "))).collect();
+        assert_eq!(order, vec!["gera G E R A", "labas L A B A S", "labas A B", "rytas R I_ T A S"]);
+        let variants = lexicon.variants();
+        assert_eq!(variants["labas"][0].symbols.join(" "), "L A B A S");
+    }
+
+    #[test]
+    fn accepts_marker_tags() {
+        let markers = MarkerTable::default();
+        let lexicon = Lexicon::parse("pauze [PAUSE]\nlabas L A B A S [NOISE]\nkosulys [COUGH] K\n");
+        assert!(lexicon.entries[0].invalid_symbols(&markers).is_empty());
+        assert!(lexicon.entries[1].invalid_symbols(&markers).is_empty());
+        assert_eq!(lexicon.entries[2].invalid_symbols(&markers), vec!["[COUGH]"]);
+        assert_eq!(lexicon.entries[0].phonemes(&markers)[0].ordinal, 37);
+    }
+}
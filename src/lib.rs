@@ -0,0 +1,52 @@
+extern crate serde_json;
+extern crate encoding;
+extern crate regex;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate log;
+#[macro_use] extern crate lazy_static;
+extern crate serde;
+extern crate libc;
+extern crate toml;
+extern crate tar;
+extern crate flate2;
+extern crate zip;
+extern crate sha2;
+
+mod decode;
+mod model_def;
+mod model_runner;
+pub mod error;
+pub mod dict_entry;
+pub mod path_encoding;
+pub mod pairing;
+pub mod manifest;
+pub mod archive;
+pub mod traversal;
+pub mod cache;
+pub mod issues;
+pub mod training_entry;
+pub mod phonemes;
+pub mod phoneme_resolvers;
+pub mod pipeline;
+pub mod lexicon;
+pub mod stats;
+pub mod config;
+pub mod spelling;
+pub mod markers;
+pub mod transcript;
+pub mod kaldi;
+pub mod writers;
+pub mod wav;
+pub mod audio_quality;
+pub mod filters;
+pub mod rate_check;
+
+pub use error::Error;
+pub use error::Result;
+pub use dict_entry::DictEntry;
+pub use training_entry::TrainingEntry;
+pub use phonemes::Phoneme;
+pub use phoneme_resolvers::PhonemeResolver;
+pub use pipeline::WalkOptions;
+pub use lexicon::Lexicon;
+pub use config::Config;
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use dict_entry::DictEntry;
+use path_encoding;
+use issues::IssueKind;
+use issues::IssueLog;
+use error::Error;
+use error::Result;
+
+/// Column names of an optional header line.
+const HEADER: [&str; 3] = ["audio", "transcript", "speaker"];
+
+/// Splits a CSV record, honouring double quoted fields with `""` escapes.
+fn split_csv(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c)
+        }
+    }
+    if quoted {
+        return Err(String::from("unterminated quoted field"));
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Fields of a manifest line, comma separated for `.csv` files and tab separated otherwise.
+fn split_record(line: &str, csv: bool) -> std::result::Result<Vec<String>, String> {
+    if csv {
+        split_csv(line)
+    } else {
+        Ok(line.split('\t').map(String::from).collect())
+    }
+}
+
+/// Reads entries from a manifest with the columns audio path, transcript and an optional speaker, instead of
+/// walking a directory. The transcript column names a transcript file if there is one at that path, otherwise
+/// it is the transcript itself. Relative paths are relative to the manifest, and so are the entry IDs.
+/// A first line naming the columns is skipped, as are empty lines and lines starting with `#`.
+pub fn read_manifest(path: &Path, issues: &mut IssueLog) -> Result<Vec<DictEntry>> {
+    let content = DictEntry::read_transcript(path)?;
+    let csv = path.extension().map(|v| v.eq_ignore_ascii_case("csv")).unwrap_or(false);
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let resolve = |value: &str| -> PathBuf {
+        let value = Path::new(value);
+        if value.is_absolute() { value.to_path_buf() } else { base.join(value) }
+    };
+
+    let mut entries: Vec<DictEntry> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let manifest_error = |message: String| Error::Manifest { path: path.to_path_buf(), line: line_no, message };
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = split_record(line, csv).map_err(&manifest_error)?;
+        if idx == 0 && fields.first().map(|v| v.trim().eq_ignore_ascii_case(HEADER[0])).unwrap_or(false) {
+            continue;
+        }
+        if fields.len() < 2 || fields.len() > HEADER.len() {
+            return Err(manifest_error(format!("expected {} to {} columns ({}), found {}",
+                2, HEADER.len(), HEADER.join(", "), fields.len())));
+        }
+        if fields[0].trim().is_empty() {
+            return Err(manifest_error(String::from("empty audio path")));
+        }
+
+        let audio = resolve(fields[0].trim());
+        let mut entry = DictEntry::new_empty();
+        entry.containing_dir = match audio.parent() {
+            Some(v) => v.to_path_buf(),
+            None => return Err(manifest_error(format!("audio path {:?} has no parent directory", audio)))
+        };
+        entry.name = match audio.file_stem() {
+            Some(v) => path_encoding::escape(v),
+            None => return Err(manifest_error(format!("audio path {:?} has no file name", audio)))
+        };
+        entry.audio_path = audio;
+
+        let transcript_file = resolve(fields[1].trim());
+        if !fields[1].trim().is_empty() && transcript_file.is_file() {
+            entry.transcript_path = transcript_file;
+            match DictEntry::read_transcript(&entry.transcript_path) {
+                Ok(v) => entry.transcript = v,
+                Err(e) => {
+                    issues.report(IssueKind::DecodeFailure, &entry.transcript_path, e)?;
+                    continue;
+                }
+            }
+        } else {
+            let mut location = path.as_os_str().to_os_string();
+            location.push(format!(":{}", line_no));
+            entry.transcript_path = PathBuf::from(location);
+            entry.transcript = fields[1].clone();
+        }
+        entry.speaker = fields.get(2)
+            .map(|v| String::from(v.trim()))
+            .filter(|v| !v.is_empty());
+        entry.id = DictEntry::relative_id(base, &entry.containing_dir, &entry.name);
+
+        if entry.is_incomplete() {
+            entry.record_incomplete(issues);
+            continue;
+        }
+        if let Some(&other) = ids.get(&entry.id) {
+            let error = Error::NamingCollision {
+                first: entries[other].audio_path.clone(),
+                second: entry.audio_path.clone()
+            };
+            issues.report(IssueKind::Collision, &entry.audio_path, error)?;
+            continue;
+        }
+        ids.insert(entry.id.clone(), entries.len());
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use issues::IssueOptions;
+
+    fn split(line: &str) -> Vec<String> {
+        split_csv(line).unwrap()
+    }
+
+    /// Reads a manifest written to a fresh directory.
+    fn read(name: &str, content: &str) -> Vec<DictEntry> {
+        let dir = std::env::temp_dir().join(format!("dict_walker_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        let entries = read_manifest(&path, &mut IssueLog::new(&IssueOptions::default())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        entries
+    }
+
+    #[test]
+    fn splits_plain_fields() {
+        assert_eq!(split("a.wav,labas rytas,spk1"), vec!["a.wav", "labas rytas", "spk1"]);
+        assert_eq!(split(""), vec![""]);
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(split("\"a,b.wav\",\"labas, rytas\""), vec!["a,b.wav", "labas, rytas"]);
+        assert_eq!(split("a.wav,\"\""), vec!["a.wav", ""]);
+        // quotes only open a field at its start
+        assert_eq!(split("a\"b.wav,x"), vec!["a\"b.wav", "x"]);
+    }
+
+    #[test]
+    fn unescapes_doubled_quotes() {
+        assert_eq!(split("a.wav,\"jis tarė \"\"labas\"\"\""), vec!["a.wav", "jis tarė \"labas\""]);
+        assert_eq!(split("\"\"\"\""), vec!["\""]);
+    }
+
+    #[test]
+    fn keeps_empty_trailing_fields() {
+        assert_eq!(split("a.wav,labas,"), vec!["a.wav", "labas", ""]);
+        assert_eq!(split("a.wav,\"labas\","), vec!["a.wav", "labas", ""]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert!(split_csv("a.wav,\"labas").is_err());
+        assert!(split_csv("a.wav,\"labas\"\"").is_err());
+    }
+
+    #[test]
+    fn skips_the_header_row() {
+        let entries = read("header.csv", "Audio,transcript,speaker\nspk1/a.wav,\"labas, rytas\",\nb.wav,diena,spk2\n");
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].id.as_str(), entries[0].transcript.as_str(), &entries[0].speaker), ("spk1/a", "labas, rytas", &None));
+        assert_eq!(entries[1].speaker, Some(String::from("spk2")));
+
+        // only the first line is a header
+        let entries = read("late.tsv", "a.wav\tlabas\naudio\tgera\n");
+        assert_eq!(entries.iter().map(|v| v.name.as_str()).collect::<Vec<&str>>(), vec!["a", "audio"]);
+    }
+}
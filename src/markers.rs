@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use phonemes::Phoneme;
+use phonemes::LAST_PHONETIC_ORDINAL;
+use error::Error;
+use error::Result;
+
+pub const PAUSE: &str = "PAUSE";
+pub const MIDWORDPAUSE: &str = "MIDWORDPAUSE";
+
+/// The marker table used when no marker file is given.
+const DEFAULT_MARKERS: &str = include_str!("../rules/markers.toml");
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Marker {
+    /// Canonical name, emitted as `[TAG]`.
+    pub tag: String,
+    pub ordinal: i8,
+    /// Forms the annotators write for this marker.
+    pub spellings: Vec<String>
+}
+
+impl Marker {
+    pub fn phoneme(&self) -> Phoneme {
+        Phoneme::marker(&self.tag, self.ordinal)
+    }
+
+    pub fn bracketed(&self) -> String {
+        format!("[{}]", self.tag)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarkerFile {
+    marker: Vec<Marker>
+}
+
+/// Marker vocabulary shared by transcript normalization and `MarkerPhonemeResolver`.
+#[derive(Debug, Clone)]
+pub struct MarkerTable {
+    markers: Vec<Marker>
+}
+
+impl Default for MarkerTable {
+    fn default() -> MarkerTable {
+        MarkerTable::parse(Path::new("rules/markers.toml"), DEFAULT_MARKERS)
+            .expect("built-in marker table is invalid")
+    }
+}
+
+impl MarkerTable {
+    pub fn load(path: &Path) -> Result<MarkerTable> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut markers_str = String::new();
+        file.read_to_string(&mut markers_str).map_err(|e| Error::io(path, e))?;
+        MarkerTable::parse(path, &markers_str)
+    }
+
+    pub fn parse(path: &Path, markers_str: &str) -> Result<MarkerTable> {
+        let file: MarkerFile = toml::from_str(markers_str)
+            .map_err(|e| Error::rule_file(path, e.to_string()))?;
+
+        let mut tags = HashSet::new();
+        let mut ordinals = HashSet::new();
+        let mut spellings = HashSet::new();
+        for marker in file.marker.iter() {
+            if !tags.insert(marker.tag.as_str()) {
+                return Err(Error::rule_file(path, format!("marker \"{}\" is defined twice", marker.tag)));
+            }
+            if marker.ordinal <= LAST_PHONETIC_ORDINAL || !ordinals.insert(marker.ordinal) {
+                return Err(Error::rule_file(path, format!("ordinal {} of marker \"{}\" is already taken", marker.ordinal, marker.tag)));
+            }
+            for spelling in marker.spellings.iter() {
+                if spelling.is_empty() || !spellings.insert(spelling.as_str()) {
+                    return Err(Error::rule_file(path, format!("spelling \"{}\" of marker \"{}\" is empty or used twice", spelling, marker.tag)));
+                }
+            }
+        }
+        for required in [PAUSE, MIDWORDPAUSE].iter() {
+            if !tags.contains(required) {
+                return Err(Error::rule_file(path, format!("required marker \"{}\" is missing", required)));
+            }
+        }
+
+        Ok(MarkerTable { markers: file.marker.clone() })
+    }
+
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    pub fn by_tag(&self, tag: &str) -> Option<&Marker> {
+        self.markers.iter().find(|v| v.tag == tag)
+    }
+
+    /// Looks up a bracketed `[TAG]`.
+    pub fn by_bracketed(&self, bracketed: &str) -> Option<&Marker> {
+        if bracketed.starts_with('[') && bracketed.ends_with(']') && bracketed.len() > 2 {
+            self.by_tag(&bracketed[1..bracketed.len() - 1])
+        } else {
+            None
+        }
+    }
+
+    pub fn by_spelling(&self, spelling: &str) -> Option<&Marker> {
+        self.markers.iter().find(|v| v.spellings.iter().any(|s| s == spelling))
+    }
+
+    pub fn pause(&self) -> Phoneme {
+        self.by_tag(PAUSE).unwrap().phoneme()
+    }
+
+    pub fn midword_pause(&self) -> Phoneme {
+        self.by_tag(MIDWORDPAUSE).unwrap().phoneme()
+    }
+}
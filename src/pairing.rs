@@ -0,0 +1,129 @@
+use std::str::FromStr;
+use regex::Regex;
+
+/// Location of one side of an entry relative to the dictionary, either a template such as `{dir}/wav/{id}.wav`
+/// or `re:` followed by a regular expression with an `id` and an optional `dir` group.
+/// `{dir}` stands for any number of directories, `{id}` for the part of a file name shared by both sides.
+#[derive(Debug, Clone)]
+pub struct FilePattern {
+    source: String,
+    regex: Regex
+}
+
+impl FilePattern {
+    fn template_regex(template: &str) -> String {
+        let mut regex = String::from("^");
+        let mut rest = template;
+        if rest.starts_with("{dir}/") {
+            // entries lying directly in the dictionary have no directory at all
+            regex.push_str("(?:(?P<dir>.*)/)?");
+            rest = &rest["{dir}/".len()..];
+        }
+        while !rest.is_empty() {
+            if rest.starts_with("{dir}") {
+                regex.push_str("(?P<dir>.*)");
+                rest = &rest["{dir}".len()..];
+            } else if rest.starts_with("{id}") {
+                regex.push_str("(?P<id>[^/]+?)");
+                rest = &rest["{id}".len()..];
+            } else {
+                let literal = rest.find('{').map(|v| v.max(1)).unwrap_or_else(|| rest.len());
+                regex.push_str(&regex::escape(&rest[..literal]));
+                rest = &rest[literal..];
+            }
+        }
+        regex.push('$');
+        regex
+    }
+
+    /// `dir` and `id` of a path relative to the dictionary, with `/` separators. `dir` is empty at the top level.
+    pub fn captures(&self, relative_path: &str) -> Option<(String, String)> {
+        self.regex.captures(relative_path).map(|caps| (
+            caps.name("dir").map(|v| String::from(v.as_str())).unwrap_or_default(),
+            String::from(&caps["id"])
+        ))
+    }
+}
+
+impl FromStr for FilePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        let regex = if let Some(v) = s.strip_prefix("re:") {
+            String::from(v)
+        } else {
+            if !s.contains("{id}") {
+                return Err(format!("\"{}\" is not a valid pairing pattern, the template lacks {{id}}.", s));
+            }
+            FilePattern::template_regex(s)
+        };
+        let regex = Regex::new(&regex).map_err(|e| format!("\"{}\" is not a valid pairing pattern: {}", s, e))?;
+        if !regex.capture_names().any(|v| v == Some("id")) {
+            return Err(format!("\"{}\" is not a valid pairing pattern, the regular expression lacks an \"id\" group.", s));
+        }
+        Ok(FilePattern { source: String::from(s), regex })
+    }
+}
+
+impl PartialEq for FilePattern {
+    fn eq(&self, other: &FilePattern) -> bool {
+        self.source == other.source
+    }
+}
+
+/// Pairing rules replacing the stem and extension matching: an audio file and a transcript belong together
+/// when both patterns yield the same `dir` and `id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairingPatterns {
+    pub audio: FilePattern,
+    pub text: FilePattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, relative_path: &str) -> Option<(String, String)> {
+        FilePattern::from_str(pattern).unwrap().captures(relative_path)
+    }
+
+    fn captured(dir: &str, id: &str) -> Option<(String, String)> {
+        Some((String::from(dir), String::from(id)))
+    }
+
+    #[test]
+    fn translates_placeholders() {
+        assert_eq!(FilePattern::template_regex("{dir}/wav/{id}.wav"), "^(?:(?P<dir>.*)/)?wav/(?P<id>[^/]+?)\\.wav$");
+        assert_eq!(FilePattern::template_regex("{dir}_{id}.txt"), "^(?P<dir>.*)_(?P<id>[^/]+?)\\.txt$");
+        assert_eq!(captures("{dir}/wav/{id}.wav", "spk1/day2/wav/a.b.wav"), captured("spk1/day2", "a.b"));
+        assert_eq!(captures("{dir}/wav/{id}.wav", "wav/a.wav"), captured("", "a"));
+        assert_eq!(captures("{id}.txt", "a.txt"), captured("", "a"));
+        // an id never spans directories
+        assert_eq!(captures("{id}.txt", "spk1/a.txt"), None);
+    }
+
+    #[test]
+    fn escapes_literal_parts() {
+        assert_eq!(FilePattern::template_regex("a+b/{id}.wav"), "^a\\+b/(?P<id>[^/]+?)\\.wav$");
+        assert_eq!(captures("a+b/{id}.wav", "a+b/x.wav"), captured("", "x"));
+        assert_eq!(captures("a+b/{id}.wav", "aab/x.wav"), None);
+        assert_eq!(captures("{id}.wav", "xxwav"), None);
+        // braces other than the placeholders are plain text
+        assert_eq!(captures("{x}/{id}.wav", "{x}/a.wav"), captured("", "a"));
+    }
+
+    #[test]
+    fn anchors_templates() {
+        assert_eq!(captures("wav/{id}.wav", "spk1/wav/a.wav"), None);
+        assert_eq!(captures("wav/{id}.wav", "wav/a.wav.bak"), None);
+        assert_eq!(captures("{dir}/wav/{id}.wav", "spk1/wav/a.wav.bak"), None);
+    }
+
+    #[test]
+    fn rejects_patterns_without_an_id() {
+        assert!(FilePattern::from_str("{dir}/a.wav").is_err());
+        assert!(FilePattern::from_str("re:^(?P<dir>.*)/a\\.wav$").is_err());
+        assert!(FilePattern::from_str("re:(").is_err());
+        assert_eq!(captures("re:^(?P<id>\\d+)\\.wav$", "12.wav"), captured("", "12"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Marks a path written by `PathEncoding::Escape` whose bytes are escaped.
+pub const ESCAPED_PREFIX: &str = "escaped:";
+
+/// How paths that are not valid UTF-8 are written to the output. Valid UTF-8 paths are written as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathEncoding {
+    /// `ESCAPED_PREFIX` followed by the path with bytes that are not UTF-8 as `\xNN` and backslashes doubled,
+    /// see `escape_bytes`. Lossless, `decode` gives the path back.
+    #[default]
+    Escape,
+    /// Bytes that are not UTF-8 become U+FFFD, which keeps the output readable but loses the exact name.
+    Lossy
+}
+
+impl FromStr for PathEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        match s {
+            "escape" => Ok(PathEncoding::Escape),
+            "lossy" => Ok(PathEncoding::Lossy),
+            _ => Err(format!("\"{}\" is not a valid path encoding, expected escape or lossy.", s))
+        }
+    }
+}
+
+impl PathEncoding {
+    pub fn encode(self, path: &Path) -> String {
+        match (self, path.to_str()) {
+            // a valid path starting with the prefix is escaped as well, so decoding stays unambiguous
+            (PathEncoding::Escape, Some(v)) if !v.starts_with(ESCAPED_PREFIX) => String::from(v),
+            (PathEncoding::Escape, _) => format!("{}{}", ESCAPED_PREFIX, escape_bytes(path.as_os_str())),
+            (PathEncoding::Lossy, _) => path.to_string_lossy().into_owned()
+        }
+    }
+
+    /// The path an `encode`d string stands for, exact for `Escape`.
+    pub fn decode(self, encoded: &str) -> PathBuf {
+        match (self, encoded.strip_prefix(ESCAPED_PREFIX)) {
+            (PathEncoding::Escape, Some(v)) => PathBuf::from(unescape_bytes(v)),
+            _ => PathBuf::from(encoded)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn os_bytes(value: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    value.as_bytes().to_vec()
+}
+
+/// Elsewhere only unpaired UTF-16 surrogates fail to decode, they are replaced.
+#[cfg(not(unix))]
+fn os_bytes(value: &OsStr) -> Vec<u8> {
+    value.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Reversible text form of any file name or path: bytes that are not UTF-8 become `\xNN`, backslashes `\\`.
+pub fn escape_bytes(value: &OsStr) -> String {
+    escape_raw(&os_bytes(value))
+}
+
+/// `escape_bytes` of a name that is no `OsStr`, such as a tar member name.
+pub fn escape_raw(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(rest) {
+            Ok(v) => (v, 0),
+            Err(e) => (std::str::from_utf8(&rest[..e.valid_up_to()]).unwrap(), e.error_len().unwrap_or(rest.len() - e.valid_up_to()))
+        };
+        result.push_str(&valid.replace('\\', "\\\\"));
+        for byte in &rest[valid.len()..valid.len() + invalid] {
+            result.push_str(&format!("\\x{:02x}", byte));
+        }
+        rest = &rest[valid.len() + invalid..];
+    }
+    result
+}
+
+/// Reverses `escape_bytes`.
+pub fn unescape_bytes(value: &str) -> OsString {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes.get(idx + 2..idx + 4)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| u8::from_str_radix(v, 16).ok());
+        match (bytes[idx], bytes.get(idx + 1), hex) {
+            (b'\\', Some(b'\\'), _) => {
+                result.push(b'\\');
+                idx += 2;
+            },
+            (b'\\', Some(b'x'), Some(byte)) => {
+                result.push(byte);
+                idx += 4;
+            },
+            (byte, _, _) => {
+                result.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    os_string(result)
+}
+
+/// Text form of a file name in entry IDs and the paths pairing patterns and globs see: valid UTF-8 as it is,
+/// anything else through `escape_bytes`.
+pub fn escape(value: &OsStr) -> String {
+    match value.to_str() {
+        Some(v) => String::from(v),
+        None => escape_bytes(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn non_utf8(bytes: &[u8]) -> OsString {
+        os_string(bytes.to_vec())
+    }
+
+    #[test]
+    fn keeps_utf8_paths() {
+        for path in ["/data/spk1/a.wav", "x\\y.wav", "C:\\corpus\\a.wav", "šaltinis/ąčę.wav", "a\\x41.wav"].iter() {
+            assert_eq!(PathEncoding::Escape.encode(Path::new(path)), *path);
+            assert_eq!(PathEncoding::Escape.decode(path), PathBuf::from(path));
+            assert_eq!(escape(OsStr::new(path)), *path);
+        }
+    }
+
+    #[test]
+    fn escapes_paths_with_the_prefix() {
+        let path = Path::new("escaped:a.wav");
+        let encoded = PathEncoding::Escape.encode(path);
+        assert_eq!(encoded, "escaped:escaped:a.wav");
+        assert_eq!(PathEncoding::Escape.decode(&encoded), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_non_utf8_paths() {
+        let paths = [
+            non_utf8(b"/data/spk\xe0/\xf0altinis.wav"),
+            non_utf8(b"\xff"),
+            non_utf8(b"back\\slash\xe8"),
+            non_utf8(b"looks\\x41like\xe8"),
+            non_utf8(b"truncated\xc4")
+        ];
+        for path in paths.iter() {
+            let encoded = PathEncoding::Escape.encode(Path::new(path));
+            assert!(encoded.starts_with(ESCAPED_PREFIX), "{}", encoded);
+            assert_eq!(PathEncoding::Escape.decode(&encoded).as_os_str(), path.as_os_str());
+            assert_eq!(unescape_bytes(&escape_bytes(path)), *path);
+        }
+        assert_eq!(PathEncoding::Escape.encode(Path::new(&paths[0])), "escaped:/data/spk\\xe0/\\xf0altinis.wav");
+        assert_eq!(escape(&paths[2]), "back\\\\slash\\xe8");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lossy_replaces_invalid_bytes() {
+        let path = non_utf8(b"spk\xe0.wav");
+        assert_eq!(PathEncoding::Lossy.encode(Path::new(&path)), "spk\u{fffd}.wav");
+    }
+
+    #[test]
+    fn round_trips_escaped_text() {
+        for value in ["", "plain", "a\\b", "\\\\", "\\x", "\\xzz", "end\\"].iter() {
+            assert_eq!(unescape_bytes(&escape_bytes(OsStr::new(value))), OsString::from(value));
+        }
+    }
+}
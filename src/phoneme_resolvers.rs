@@ -1,12 +1,11 @@
 use phonemes::Phoneme;
 use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 use std::fs::File;
-use std::io;
-use std::io::Read;
-use regex::Regex;
+use lexicon::Lexicon;
+use markers::MarkerTable;
 use std::str::FromStr;
-use std::error::Error;
 use libc::c_void;
 use libc::c_char;
 use libc::size_t;
@@ -16,85 +15,111 @@ use std::ffi::CStr;
 use std::collections::HashSet;
 use model_def::ModelDef;
 use std::iter::FromIterator;
+use cache::content_hash;
+use cache::hash_file;
+use error::Error;
+use error::Result;
 
 pub trait PhonemeResolver {
+    /// Short name recorded as the source of the words this resolver answers, e.g. `dict:lexicon.txt`.
+    fn name(&self) -> String;
+
     fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>>;
+
+    /// Changes whenever the answers of the resolver may change, see `cache::EntryCache`.
+    /// Resolvers without data files of their own need not override this.
+    fn fingerprint(&self) -> Result<String> {
+        Ok(self.name())
+    }
+
+    /// Changes whenever the answer for this one word may change, for resolvers which can tell without
+    /// `fingerprint` covering all their data. `None` leaves it to `fingerprint`.
+    fn word_fingerprint(&self, _graphemes: &str) -> Option<String> {
+        None
+    }
+
+    /// Every known pronunciation, preferred one first. Resolvers with a single answer need not override this.
+    fn resolve_all(&self, graphemes: &str) -> Option<Vec<Vec<Phoneme>>> {
+        self.resolve(graphemes).map(|v| vec![v])
+    }
 }
 
 pub struct DictionaryPhonemeResolver {
-    dict: HashMap<String, Vec<Phoneme>>
+    path: PathBuf,
+    dict: HashMap<String, Vec<Vec<Phoneme>>>
 }
 
 impl DictionaryPhonemeResolver {
-    pub fn load(path: &Path) -> io::Result<DictionaryPhonemeResolver> {
-        let mut file = File::open(path)?;
-        let mut dict_str = String::new();
-        file.read_to_string(&mut dict_str)?;
-
-        let mut dict: HashMap<String, Vec<Phoneme>> = HashMap::new();
-
-        lazy_static! {
-            static ref postfix_accent_re: Regex = Regex::new(r"^(?P<graph>[^ ]+) +(?P<accent>.+)$").unwrap(); // TODO fix var and named cap group names
-        }
-
-        for entry in dict_str.split("\n") {
-            let caps = match postfix_accent_re.captures(entry) {
-                Some(v) => v,
-                None => {
-                    warn!("Cannot parse dictionary line \"{}\" as a dictionary entry", entry);
-                    continue;
-                }
-            };
-
-            let gr = caps.get(1).unwrap().as_str();
-            let ph: Vec<Phoneme> = caps.get(2).unwrap()
-                .as_str().split_whitespace()
-                .map(|v| Phoneme::from_str(v).unwrap())
-                .collect();
-
-            dict.insert(gr.to_string(), ph);
+    /// Loads a dictionary whose pronunciations may use the tags of `markers`.
+    pub fn load(path: &Path, markers: &MarkerTable) -> Result<DictionaryPhonemeResolver> {
+        let lexicon = Lexicon::load(path)?;
+        for (line, entry) in lexicon.malformed.iter() {
+            warn!("Cannot parse dictionary line {} \"{}\" as a dictionary entry", line, entry);
         }
 
+        let dict: HashMap<String, Vec<Vec<Phoneme>>> = lexicon.variants()
+            .into_iter()
+            .map(|(word, entries)| (word.to_string(), entries.iter().map(|v| v.phonemes(markers)).collect()))
+            .collect();
 
         Ok(DictionaryPhonemeResolver {
+            path: path.to_path_buf(),
             dict
         })
     }
 }
 
 impl PhonemeResolver for DictionaryPhonemeResolver {
+    fn name(&self) -> String {
+        format!("dict:{}", self.path.display())
+    }
+
+    fn fingerprint(&self) -> Result<String> {
+        Ok(format!("{} {}", self.name(), hash_file(&self.path)?))
+    }
+
+    /// The entries of the word, so editing the dictionary only affects the words whose entries changed.
+    fn word_fingerprint(&self, graphemes: &str) -> Option<String> {
+        let variants: Vec<String> = self.dict.get(graphemes)
+            .map(|v| v.iter().map(|ph| ph.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(" ")).collect())
+            .unwrap_or_default();
+        Some(format!("{} {}", self.name(), content_hash(variants.join("|").as_bytes())))
+    }
+
     fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>> {
-        match self.dict.get(graphemes) {
-            Some(v) => Some(v.clone().to_vec()),
-            None => None
-        }
+        self.dict.get(graphemes).and_then(|v| v.first()).cloned()
+    }
+
+    fn resolve_all(&self, graphemes: &str) -> Option<Vec<Vec<Phoneme>>> {
+        self.dict.get(graphemes).cloned()
     }
 }
 
-pub struct MarkerPhonemeResolver {}
+#[derive(Default)]
+pub struct MarkerPhonemeResolver {
+    markers: MarkerTable
+}
 
 impl MarkerPhonemeResolver {
-    pub fn new() -> MarkerPhonemeResolver {
-        MarkerPhonemeResolver{}
+    pub fn new(markers: MarkerTable) -> MarkerPhonemeResolver {
+        MarkerPhonemeResolver{
+            markers
+        }
     }
 }
 
 impl PhonemeResolver for MarkerPhonemeResolver {
+    fn name(&self) -> String {
+        String::from("marker")
+    }
+
     fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>> {
-        match graphemes.starts_with("[") {
-            true => {
-                let ph = Phoneme::from_str(graphemes).unwrap();
-                if ph.valid {
-                    Some(vec![ph])
-                } else {
-                    None
-                }
-            },
-            false => None
-        }
+        self.markers.by_bracketed(graphemes)
+            .map(|v| vec![v.phoneme()])
     }
 }
 
+#[derive(Default)]
 pub struct DeadEndPhonemeResolver {}
 
 impl DeadEndPhonemeResolver {
@@ -104,40 +129,42 @@ impl DeadEndPhonemeResolver {
 }
 
 impl PhonemeResolver for DeadEndPhonemeResolver {
+    fn name(&self) -> String {
+        String::from("deadend")
+    }
+
     fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>> {
         warn!("Failed to resolve phonemes for word \"{}\"", graphemes);
         Some(vec![Phoneme::from_str(graphemes).unwrap()])
     }
 }
 
-pub struct DummyPhonemeResolver {}
-
-impl DummyPhonemeResolver {
-    pub fn new() -> DummyPhonemeResolver {
-        DummyPhonemeResolver{}
-    }
-}
-
-impl PhonemeResolver for DummyPhonemeResolver {
-    fn resolve(&self, _graphemes: &str) -> Option<Vec<Phoneme>> {
-        None
-    }
-}
-
 pub struct TensorflowPhonemeResolver {
+    path: PathBuf,
     ptr: *const c_void,
-    allowed_tokens: HashSet<String>
+    allowed_tokens: HashSet<String>,
+    markers: MarkerTable
 }
 
 impl TensorflowPhonemeResolver {
-    pub fn load(model_folder_path: &Path) -> Result<TensorflowPhonemeResolver, Box<dyn Error>> {
-        let model_def: ModelDef = serde_json::from_reader(
-            File::open(model_folder_path.join("model.json")).unwrap()
-        ).unwrap();
-        let path: CString = CString::new(model_folder_path.as_os_str().to_str().unwrap())?;
+    /// Loads a model whose output may use the tags of `markers`.
+    pub fn load(model_folder_path: &Path, markers: &MarkerTable) -> Result<TensorflowPhonemeResolver> {
+        let model_def_path = model_folder_path.join("model.json");
+        let model_def_file = File::open(&model_def_path)
+            .map_err(|e| Error::model_load(&model_def_path, e.to_string()))?;
+        let model_def: ModelDef = serde_json::from_reader(model_def_file)
+            .map_err(|e| Error::model_load(&model_def_path, e.to_string()))?;
+        let path_str = match model_folder_path.to_str() {
+            Some(v) => v,
+            None => return Err(Error::NonUnicodePath { path: model_folder_path.to_path_buf() })
+        };
+        let path: CString = CString::new(path_str)
+            .map_err(|e| Error::model_load(model_folder_path, e.to_string()))?;
         Ok(TensorflowPhonemeResolver {
+            path: model_folder_path.to_path_buf(),
             ptr: unsafe { model_runner::getModelRunnerInstance(path.as_ptr()) },
-            allowed_tokens: HashSet::from_iter(model_def.in_tokens)
+            allowed_tokens: HashSet::from_iter(model_def.in_tokens),
+            markers: markers.clone()
         })
     }
 }
@@ -151,23 +178,20 @@ impl Drop for TensorflowPhonemeResolver {
 }
 
 impl PhonemeResolver for TensorflowPhonemeResolver {
-    fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>> {
-        if graphemes.contains("[midwordpause]") {
-            let mut result: Vec<Phoneme> = vec![];
-            for (idx, part) in graphemes.split("[midwordpause]").enumerate() {
-                match self.resolve(part) {
-                    Some(mut r) => {
-                        if idx != 0 {
-                            result.push(Phoneme::from_str("[MIDWORDPAUSE]").unwrap());
-                        }
-                        r.drain(0..).for_each(|v|result.push(v));
-                    },
-                    None => return None
-                }
-            }
-            return Some(result);
+    fn name(&self) -> String {
+        format!("tf:{}", self.path.display())
+    }
+
+    fn fingerprint(&self) -> Result<String> {
+        let mut fingerprint = self.name();
+        for file in ["model.json", "encoder_inference_model.pb", "decoder_inference_model.pb"].iter() {
+            fingerprint.push(' ');
+            fingerprint.push_str(&hash_file(&self.path.join(file))?);
         }
+        Ok(fingerprint)
+    }
 
+    fn resolve(&self, graphemes: &str) -> Option<Vec<Phoneme>> {
         let mut phonemes: Vec<String> = Vec::new();
         let mut _graphemes: Vec<CString> = Vec::new();
         let mut last = 0;
@@ -197,9 +221,9 @@ impl PhonemeResolver for TensorflowPhonemeResolver {
             );
 
             for i in 0..result_size {
-                let c_str: &CStr = CStr::from_ptr(*result.offset(i as isize));
-                phonemes.push(c_str.to_str().unwrap().clone().to_owned());
-                libc::free(*result.offset(i as isize) as *mut c_void);
+                let c_str: &CStr = CStr::from_ptr(*result.add(i));
+                phonemes.push(c_str.to_str().unwrap().to_owned());
+                libc::free(*result.add(i) as *mut c_void);
             }
             libc::free(result as *mut c_void);
         }
@@ -207,7 +231,7 @@ impl PhonemeResolver for TensorflowPhonemeResolver {
         let result: Vec<Phoneme> = phonemes.iter()
             .skip(1)
             .take(phonemes.len()-2)
-            .map(|val| Phoneme::from_str(val).unwrap())
+            .map(|val| Phoneme::from_symbol_in(val, false, &self.markers))
             .collect();
 
         Some(result)
@@ -3,6 +3,7 @@ use std::fmt::Formatter;
 use std::fmt::Error;
 use std::str::FromStr;
 use serde::Serializer;
+use markers::MarkerTable;
 
 #[derive(Debug)]
 pub struct Phoneme {
@@ -12,10 +13,40 @@ pub struct Phoneme {
     pub valid: bool
 }
 
+/// Highest ordinal of the phonetic inventory, marker ordinals come after it.
+pub const LAST_PHONETIC_ORDINAL: i8 = 36;
+
 impl Phoneme {
+    /// Builds the phoneme of a non-speech marker, see `markers::MarkerTable`.
+    pub fn marker(tag: &str, ordinal: i8) -> Phoneme {
+        Phoneme {
+            symbol: String::from(tag),
+            ordinal,
+            accented: false,
+            valid: true
+        }
+    }
+
+    /// Whether this is a vowel of the phonetic inventory, i.e. something that can carry stress.
+    pub fn is_vowel(&self) -> bool {
+        match self.symbol.as_ref() {
+            "A" | "A_" | "E" | "E_" | "E3_" | "I" | "I_" | "IO_" | "IU" | "IU_" | "O_" | "U" | "U_" => self.valid,
+            _ => false
+        }
+    }
+
+    /// `from_symbol` for dictionaries and model output, which may also hold marker tags (`[PAUSE]`) from the
+    /// marker table, and a lone space for a pause.
+    pub fn from_symbol_in(symbol: &str, accent: bool, markers: &MarkerTable) -> Phoneme {
+        match (symbol, markers.by_bracketed(symbol)) {
+            (" ", _) => markers.pause(),
+            (_, Some(marker)) => marker.phoneme(),
+            _ => Phoneme::from_symbol(symbol, accent)
+        }
+    }
+
     pub fn from_symbol(symbol: &str, accent: bool) -> Phoneme {
         match symbol {
-            " " => Phoneme::from_symbol("[PAUSE]", accent),
             "A" => Phoneme {
                 symbol: String::from("A"),
                 ordinal: 1,
@@ -232,78 +263,6 @@ impl Phoneme {
                 accented: accent,
                 valid: true
             },
-            "[PAUSE]" => Phoneme {
-                symbol: String::from("PAUSE"),
-                ordinal: 37,
-                accented: accent,
-                valid: true
-            },
-            "[INHALE]" => Phoneme {
-                symbol: String::from("INHALE"),
-                ordinal: 38,
-                accented: accent,
-                valid: true
-            },
-            "[EXHALE]" => Phoneme {
-                symbol: String::from("EXHALE"),
-                ordinal: 39,
-                accented: accent,
-                valid: true
-            },
-            "[SWALLOW]" => Phoneme {
-                symbol: String::from("SWALLOW"),
-                ordinal: 40,
-                accented: accent,
-                valid: true
-            },
-            "[SMACK]" => Phoneme {
-                symbol: String::from("SMACK"),
-                ordinal: 41,
-                accented: accent,
-                valid: true
-            },
-            "[CHAIR]" => Phoneme {
-                symbol: String::from("CHAIR"),
-                ordinal: 42,
-                accented: accent,
-                valid: true
-            },
-            "[STOMACH]" => Phoneme {
-                symbol: String::from("STOMACH"),
-                ordinal: 43,
-                accented: accent,
-                valid: true
-            },
-            "[PAGE]" => Phoneme {
-                symbol: String::from("PAGE"),
-                ordinal: 44,
-                accented: accent,
-                valid: true
-            },
-            "[DOOR]" => Phoneme {
-                symbol: String::from("DOOR"),
-                ordinal: 45,
-                accented: accent,
-                valid: true
-            },
-            "[EH]" => Phoneme {
-                symbol: String::from("EH"),
-                ordinal: 46,
-                accented: accent,
-                valid: true
-            },
-            "[MIDWORDPAUSE]" => Phoneme {
-                symbol: String::from("MIDWORDPAUSE"),
-                ordinal: 47,
-                accented: accent,
-                valid: true
-            },
-            "[NOISE]" => Phoneme {
-                symbol: String::from("NOISE"),
-                ordinal: 48,
-                accented: accent,
-                valid: true
-            },
             _ => Phoneme {
                 symbol: format!("ERR-{}", symbol),
                 ordinal: -1,
@@ -351,3 +310,27 @@ impl serde::Serialize for Phoneme {
         serializer.serialize_str(&self.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_marker_tags_from_the_table() {
+        let markers = MarkerTable::default();
+        let pause = Phoneme::from_symbol_in("[PAUSE]", false, &markers);
+        assert_eq!((pause.symbol.as_str(), pause.ordinal, pause.valid), ("PAUSE", 37, true));
+        assert_eq!(Phoneme::from_symbol_in("[NOISE]", false, &markers).ordinal, 48);
+        assert_eq!(Phoneme::from_symbol_in(" ", false, &markers).symbol, "PAUSE");
+        assert!(!Phoneme::from_symbol_in("[COUGH]", false, &markers).valid);
+        assert!(!Phoneme::from_symbol_in("PAUSE", false, &markers).valid);
+    }
+
+    #[test]
+    fn keeps_the_phonetic_inventory() {
+        let markers = MarkerTable::default();
+        let a = Phoneme::from_symbol_in("A", true, &markers);
+        assert_eq!((a.ordinal, a.accented, a.valid), (1, true, true));
+        assert_eq!(Phoneme::from_symbol_in("Q", false, &markers).symbol, "ERR-Q");
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use dict_entry::DictEntry;
+use dict_entry::PairingMode;
+use pairing::PairingPatterns;
+use traversal::TraversalOptions;
+use manifest;
+use archive;
+use cache;
+use cache::EntryCache;
+use issues::IssueKind;
+use issues::IssueLog;
+use issues::IssueOptions;
+use training_entry::TrainingEntry;
+use training_entry::TranscriptRules;
+use training_entry::EntryOptions;
+use spelling::SpellingRules;
+use markers::MarkerTable;
+use phoneme_resolvers::PhonemeResolver;
+use phoneme_resolvers::DictionaryPhonemeResolver;
+use phoneme_resolvers::TensorflowPhonemeResolver;
+use phoneme_resolvers::MarkerPhonemeResolver;
+use phoneme_resolvers::DeadEndPhonemeResolver;
+use kaldi::KaldiWriter;
+use filters::EntryFilters;
+use audio_quality::AnalysisOptions;
+use rate_check;
+use rate_check::RateCheckOptions;
+use writers::DatasetWriter;
+use writers::DataDirOptions;
+use writers::Speakers;
+use writers::JsonWriter;
+use writers::JsonlWriter;
+use writers::NemoWriter;
+use writers::EspnetWriter;
+use error::Error;
+use error::Result;
+
+/// Where the text <-> audio dictionary lives and which files belong to it.
+#[derive(Debug, Clone)]
+pub struct InputOptions {
+    /// Walked for entries, or only the root speakers are counted from when `manifest` is set.
+    pub dictionary: PathBuf,
+    /// Read the entries from this manifest instead of walking `dictionary`, see `manifest::read_manifest`.
+    pub manifest: Option<PathBuf>,
+    pub audio_exts: HashSet<String>,
+    pub text_exts: HashSet<String>,
+    pub pairing: PairingMode,
+    /// Pairing rules replacing `pairing` and the extension sets.
+    pub patterns: Option<PairingPatterns>,
+    /// Which files of `dictionary` are walked.
+    pub traversal: TraversalOptions,
+    /// Read the WAV header of every audio file, dropping entries whose header is malformed.
+    pub probe_audio: bool,
+    /// Decode the samples of every audio file and measure their quality, implies `probe_audio`.
+    pub analysis: Option<AnalysisOptions>
+}
+
+/// One link of the resolver chain, written `marker`, `dict:PATH`, `tf:FOLDER` or `deadend`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolverSpec {
+    Marker,
+    Dictionary(PathBuf),
+    Tensorflow(PathBuf),
+    DeadEnd
+}
+
+impl FromStr for ResolverSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        match (parts.next().unwrap_or(""), parts.next()) {
+            ("marker", None) => Ok(ResolverSpec::Marker),
+            ("deadend", None) => Ok(ResolverSpec::DeadEnd),
+            ("dict", Some(path)) if !path.is_empty() => Ok(ResolverSpec::Dictionary(PathBuf::from(path))),
+            ("tf", Some(path)) if !path.is_empty() => Ok(ResolverSpec::Tensorflow(PathBuf::from(path))),
+            _ => Err(format!("\"{}\" is not a valid resolver, expected marker, dict:PATH, tf:FOLDER or deadend.", s))
+        }
+    }
+}
+
+impl ResolverSpec {
+    /// Parses a comma delimited chain, e.g. `marker,dict:lex1.txt,dict:lex2.txt,tf:model/,deadend`.
+    pub fn parse_chain(chain: &str) -> std::result::Result<Vec<ResolverSpec>, String> {
+        chain.split(',').map(ResolverSpec::from_str).collect()
+    }
+}
+
+/// The resolver chain, asked in order until one resolver knows the word.
+#[derive(Debug, Clone)]
+pub struct ResolverOptions {
+    pub chain: Vec<ResolverSpec>
+}
+
+impl ResolverOptions {
+    /// The classic chain: dictionary and seq2seq model if given, then markers and the dead end.
+    pub fn new(phoneme_dictionary: Option<PathBuf>, model: Option<PathBuf>) -> ResolverOptions {
+        let mut chain = Vec::new();
+        if let Some(path) = phoneme_dictionary {
+            chain.push(ResolverSpec::Dictionary(path));
+        }
+        if let Some(path) = model {
+            chain.push(ResolverSpec::Tensorflow(path));
+        }
+        chain.push(ResolverSpec::Marker);
+        chain.push(ResolverSpec::DeadEnd);
+        ResolverOptions { chain }
+    }
+}
+
+impl Default for ResolverOptions {
+    fn default() -> ResolverOptions {
+        ResolverOptions::new(None, None)
+    }
+}
+
+/// Corpus specific transcript clean-up rule files.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptOptions {
+    pub spelling_rules: Option<PathBuf>,
+    pub markers: Option<PathBuf>
+}
+
+/// Layout of the output, see `writers::DatasetWriter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One pretty printed JSON array, written once every entry is converted.
+    #[default]
+    Json,
+    /// One JSON object per line, written and flushed as soon as the entry is converted.
+    Jsonl,
+    /// NeMo manifest lines with the audio duration.
+    Nemo,
+    /// ESPnet `data/` directory.
+    Espnet,
+    /// Kaldi data directory along with its `data/local/dict` files.
+    Kaldi
+}
+
+impl OutputFormat {
+    /// Whether the output is a directory rather than a single file.
+    pub fn is_directory(self) -> bool {
+        matches!(self, OutputFormat::Espnet | OutputFormat::Kaldi)
+    }
+
+    /// Where the output goes when no `--output` is given.
+    pub fn default_output(self) -> &'static str {
+        if self.is_directory() { "./data" } else { "./output.json" }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "nemo" => Ok(OutputFormat::Nemo),
+            "espnet" => Ok(OutputFormat::Espnet),
+            "kaldi" => Ok(OutputFormat::Kaldi),
+            _ => Err(format!("\"{}\" is not a valid output format, expected json, jsonl, nemo, espnet or kaldi.", s))
+        }
+    }
+}
+
+/// Everything needed to walk a dictionary and write the training JSON.
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    pub input: InputOptions,
+    pub transcript: TranscriptOptions,
+    pub resolvers: ResolverOptions,
+    pub entry: EntryOptions,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub data_dir: DataDirOptions,
+    pub filters: EntryFilters,
+    pub rate_check: RateCheckOptions,
+    /// Reuse and update the converted transcripts stored here, see `cache::EntryCache`.
+    pub cache: Option<PathBuf>,
+    pub issues: IssueOptions,
+    pub force: bool
+}
+
+/// Builds the resolvers of the chain in order. A chain without `deadend` fails on unresolved words.
+pub fn build_resolvers(options: &ResolverOptions, markers: &MarkerTable) -> Result<Vec<Box<dyn PhonemeResolver>>> {
+    let mut resolvers: Vec<Box<dyn PhonemeResolver>> = Vec::new();
+    for spec in options.chain.iter() {
+        resolvers.push(match spec {
+            ResolverSpec::Marker => Box::new(MarkerPhonemeResolver::new(markers.clone())),
+            ResolverSpec::Dictionary(path) => Box::new(DictionaryPhonemeResolver::load(path, markers)?),
+            ResolverSpec::Tensorflow(path) => Box::new(TensorflowPhonemeResolver::load(path, markers)?),
+            ResolverSpec::DeadEnd => Box::new(DeadEndPhonemeResolver::new())
+        });
+    }
+    Ok(resolvers)
+}
+
+pub fn load_transcript_rules(options: &TranscriptOptions) -> Result<TranscriptRules> {
+    Ok(TranscriptRules {
+        spelling: match options.spelling_rules {
+            Some(ref path) => SpellingRules::load(path)?,
+            None => SpellingRules::default()
+        },
+        markers: match options.markers {
+            Some(ref path) => MarkerTable::load(path)?,
+            None => MarkerTable::default()
+        }
+    })
+}
+
+/// Collects the complete entries of the dictionary or manifest, recording the problems found on the way in `issues`.
+pub fn collect_entries(options: &InputOptions, issues: &mut IssueLog) -> Result<Vec<DictEntry>> {
+    let mut entries = match (&options.manifest, &options.patterns) {
+        (Some(manifest), _) => manifest::read_manifest(manifest, issues)?,
+        (None, Some(patterns)) => DictEntry::collect_by_patterns(&options.dictionary, patterns, &options.traversal, issues)?,
+        (None, None) => DictEntry::collect_entries(&options.dictionary, &options.audio_exts, &options.text_exts, options.pairing, &options.traversal, issues)?
+    };
+    if options.probe_audio || options.analysis.is_some() {
+        // archive members are read in one pass over the archive rather than looked up one by one
+        let mut archived: HashMap<PathBuf, Result<()>> = HashMap::new();
+        if options.manifest.is_none() && archive::is_archive(&options.dictionary) {
+            let mut by_address: HashMap<PathBuf, &mut DictEntry> = entries.iter_mut()
+                .map(|v| (v.audio_path.clone(), v))
+                .collect();
+            archive::for_each_member(&options.dictionary, |member, reader| {
+                let address = archive::address(&options.dictionary, member);
+                if let Some(entry) = by_address.get_mut(&address) {
+                    let bytes = archive::read_member(&options.dictionary, member, reader)?;
+                    archived.insert(address, entry.read_audio_bytes(&bytes, options.analysis.as_ref()));
+                }
+                Ok(())
+            })?;
+        }
+        let mut probe = |entry: &mut DictEntry| match (archived.remove(&entry.audio_path), &options.analysis) {
+            (Some(result), _) => result,
+            (None, Some(analysis)) => entry.analyze_audio(analysis),
+            (None, None) => entry.probe_audio()
+        };
+        entries.retain_mut(|entry| match probe(entry) {
+            Ok(()) => true,
+            Err(e) => {
+                issues.record(IssueKind::DefectiveAudio, &entry.audio_path, e.to_string());
+                false
+            }
+        });
+    }
+    Ok(entries)
+}
+
+/// Converts an entry, through the cache if there is one. Words no resolver knows are reported in `issues`, the
+/// entry is `None` when it is skipped for them. Words only the dead end answered are recorded.
+pub fn convert_entry(de: &DictEntry, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], options: &EntryOptions, cache: Option<&mut EntryCache>, issues: &mut IssueLog) -> Result<Option<TrainingEntry>> {
+    // the word records tell which words went to the dead end
+    let sourced = EntryOptions { provenance: true, ..options.clone() };
+    let converted = match cache {
+        Some(cache) => cache.construct(de.clone(), rules, resolvers, &sourced),
+        None => TrainingEntry::construct(de.clone(), rules, resolvers, &sourced)
+    };
+    let mut entry = match converted {
+        Ok(v) => v,
+        Err(e @ Error::UnresolvedWord { .. }) => {
+            issues.report(IssueKind::UnresolvedWord, &de.transcript_path, e)?;
+            return Ok(None);
+        },
+        Err(e) => return Err(e)
+    };
+    for word in entry.words.iter().flatten().filter(|v| v.resolver == DeadEndPhonemeResolver::new().name()) {
+        issues.record(IssueKind::UnresolvedWord, &de.transcript_path, format!("only the dead end resolved \"{}\"", word.word));
+    }
+    if !options.provenance {
+        entry.words = None;
+    }
+    Ok(Some(entry))
+}
+
+/// Walks the dictionary and converts every complete entry into a `TrainingEntry`.
+pub fn collect_training_entries(options: &InputOptions, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], entry_options: &EntryOptions, issues: &mut IssueLog) -> Result<Vec<TrainingEntry>> {
+    let mut entries = Vec::new();
+    for de in collect_entries(options, issues)? {
+        if let Some(entry) = convert_entry(&de, rules, resolvers, entry_options, None, issues)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Creates the writer of `options.format` at `options.output`.
+pub fn create_writer(options: &WalkOptions, markers: &MarkerTable) -> Result<Box<dyn DatasetWriter>> {
+    let output = &options.output;
+    Ok(match options.format {
+        OutputFormat::Json => Box::new(JsonWriter::new(output)),
+        OutputFormat::Jsonl => Box::new(JsonlWriter::create(output)?),
+        OutputFormat::Nemo => Box::new(NemoWriter::create(output)?),
+        OutputFormat::Espnet => Box::new(EspnetWriter::new(output, &options.input.dictionary, &options.data_dir)),
+        OutputFormat::Kaldi => Box::new(KaldiWriter::new(output, &options.input.dictionary, &options.data_dir, markers))
+    })
+}
+
+/// Runs the whole walk: resolvers, collection, conversion and output. Returns the number of written entries.
+/// Directory formats count as existing once their `wav.scp` does.
+pub fn run(options: &WalkOptions) -> Result<usize> {
+    let existing = if options.format.is_directory() {
+        options.output.join("wav.scp")
+    } else {
+        options.output.clone()
+    };
+    if !options.force && existing.exists() {
+        return Err(Error::OutputExists { path: existing });
+    }
+
+    let rules = load_transcript_rules(&options.transcript)?;
+    let resolvers = build_resolvers(&options.resolvers, &rules.markers)?;
+    let entry_options = match options.format {
+        // the lexicon is built from the word records
+        OutputFormat::Kaldi => EntryOptions { provenance: true, ..options.entry.clone() },
+        _ => options.entry.clone()
+    };
+    let mut input = options.input.clone();
+    input.probe_audio |= options.filters.needs_audio() || options.rate_check.enabled();
+    // the manifest writers could only read the duration of archive members one by one
+    input.probe_audio |= archive::is_archive(&input.dictionary) && input.manifest.is_none() &&
+        matches!(options.format, OutputFormat::Nemo | OutputFormat::Espnet);
+    if options.filters.needs_quality() && input.analysis.is_none() {
+        input.analysis = Some(AnalysisOptions::default());
+    }
+
+    let mut cache = match options.cache {
+        Some(ref path) => Some(EntryCache::load(path, cache::rules_fingerprint(&options.transcript, &entry_options)?, &resolvers)?),
+        None => None
+    };
+
+    let mut issues = IssueLog::new(&options.issues);
+    let mut writer = create_writer(options, &rules.markers)?;
+    // the speaking rate check needs every entry before it can tell which ones to write
+    let mut checked: Vec<(DictEntry, TrainingEntry)> = Vec::new();
+    let mut dropped = 0;
+    for de in collect_entries(&input, &mut issues)? {
+        if let Some(reason) = options.filters.check_audio(&de) {
+            info!("Dropping {:?}: {}", de.audio_path, reason);
+            dropped += 1;
+            continue;
+        }
+        let entry = match convert_entry(&de, &rules, &resolvers, &entry_options, cache.as_mut(), &mut issues)? {
+            Some(v) => v,
+            None => continue
+        };
+        if let Some(reason) = options.filters.check_entry(&entry) {
+            info!("Dropping {:?}: {}", de.audio_path, reason);
+            dropped += 1;
+            continue;
+        }
+        if options.rate_check.enabled() {
+            checked.push((de, entry));
+        } else {
+            writer.add(&de, &entry)?;
+        }
+    }
+    if dropped > 0 {
+        info!("Dropped {} entries by the selection rules.", dropped);
+    }
+
+    if options.rate_check.enabled() {
+        let speakers = Speakers::new(&options.input.dictionary, &options.data_dir);
+        let excluded = rate_check::check(&checked, &speakers, &options.rate_check)?;
+        for (idx, (de, entry)) in checked.iter().enumerate() {
+            if !excluded.contains(&idx) {
+                writer.add(de, entry)?;
+            }
+        }
+        if !excluded.is_empty() {
+            info!("Excluded {} speaking rate outliers.", excluded.len());
+        }
+    }
+    let written = writer.finish()?;
+
+    if let Some(ref mut cache) = cache {
+        cache.save()?;
+        info!("Reused {} cached transcripts, converted {}.", cache.hits, cache.misses);
+    }
+    issues.finish()?;
+    Ok(written)
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use dict_entry::DictEntry;
+use training_entry::TrainingEntry;
+use writers::Speakers;
+use error::Error;
+use error::Result;
+
+/// Speaking rate QA: utterances whose phonemes per second lie far from the median of their speaker
+/// are likely paired with the wrong audio.
+#[derive(Debug, Clone)]
+pub struct RateCheckOptions {
+    /// Where to write the flagged utterances.
+    pub report: Option<PathBuf>,
+    /// Modified z-score (`0.6745 * |rate - median| / MAD`) above which an utterance is flagged.
+    pub threshold: f64,
+    /// Leave flagged utterances out of the output.
+    pub exclude: bool
+}
+
+impl Default for RateCheckOptions {
+    fn default() -> RateCheckOptions {
+        RateCheckOptions { report: None, threshold: 3.5, exclude: false }
+    }
+}
+
+impl RateCheckOptions {
+    pub fn enabled(&self) -> bool {
+        self.report.is_some() || self.exclude
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateOutlier {
+    /// Index of the utterance in the checked list.
+    pub index: usize,
+    pub speaker: String,
+    pub audio_path: String,
+    pub rate: f64,
+    pub speaker_median: f64,
+    pub score: f64
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Median and median absolute deviation of the rates of one speaker, `None` when they cannot be judged:
+/// fewer than three of them, or all the same rate.
+fn spread(rates: &[f64]) -> Option<(f64, f64)> {
+    if rates.len() < 3 {
+        return None;
+    }
+    let center = median(&mut rates.to_vec());
+    let mad = median(&mut rates.iter().map(|v| (v - center).abs()).collect::<Vec<f64>>());
+    if mad == 0.0 {
+        None
+    } else {
+        Some((center, mad))
+    }
+}
+
+/// Flags the outliers among probed entries. Speakers with fewer than three rated utterances, or whose
+/// utterances all share the same rate, cannot be judged and are skipped.
+pub fn find_outliers(entries: &[(DictEntry, TrainingEntry)], speakers: &Speakers, threshold: f64) -> Vec<RateOutlier> {
+    let mut by_speaker: BTreeMap<String, Vec<(usize, f64)>> = BTreeMap::new();
+    for (index, (de, entry)) in entries.iter().enumerate() {
+        if let Some(rate) = entry.phoneme_rate() {
+            by_speaker.entry(speakers.speaker(de)).or_default().push((index, rate));
+        }
+    }
+
+    let mut outliers = Vec::new();
+    for (speaker, rates) in by_speaker {
+        let (speaker_median, mad) = match spread(&rates.iter().map(|v| v.1).collect::<Vec<f64>>()) {
+            Some(v) => v,
+            None => {
+                debug!("Speaker \"{}\" has {} rated utterances, too few or too alike to check", speaker, rates.len());
+                continue;
+            }
+        };
+
+        for (index, rate) in rates {
+            let score = 0.6745 * (rate - speaker_median).abs() / mad;
+            if score > threshold {
+                outliers.push(RateOutlier {
+                    index,
+                    speaker: speaker.clone(),
+                    audio_path: entries[index].1.audio_path.clone(),
+                    rate,
+                    speaker_median,
+                    score
+                });
+            }
+        }
+    }
+    outliers
+}
+
+/// Writes the outliers as tab separated values with a header line.
+pub fn write_report(outliers: &[RateOutlier], path: &Path) -> Result<()> {
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut output = BufWriter::new(file);
+    writeln!(output, "speaker\taudio_path\tphonemes_per_second\tspeaker_median\tscore").map_err(|e| Error::io(path, e))?;
+    for v in outliers {
+        writeln!(output, "{}\t{}\t{:.2}\t{:.2}\t{:.2}", v.speaker, v.audio_path, v.rate, v.speaker_median, v.score)
+            .map_err(|e| Error::io(path, e))?;
+    }
+    output.flush().map_err(|e| Error::io(path, e))
+}
+
+/// Runs the check on the converted entries, writes the report and returns the indices of the utterances to leave out.
+pub fn check(entries: &[(DictEntry, TrainingEntry)], speakers: &Speakers, options: &RateCheckOptions) -> Result<HashSet<usize>> {
+    let outliers = find_outliers(entries, speakers, options.threshold);
+    for v in outliers.iter() {
+        warn!("Likely transcript/audio mismatch {:?}: {:.1} phonemes per second, speaker \"{}\" median {:.1}",
+            v.audio_path, v.rate, v.speaker, v.speaker_median);
+    }
+    if let Some(ref path) = options.report {
+        write_report(&outliers, path)?;
+        info!("Wrote {} speaking rate outliers to {:?}.", outliers.len(), path);
+    }
+
+    if options.exclude {
+        Ok(outliers.iter().map(|v| v.index).collect())
+    } else {
+        Ok(HashSet::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phonemes::Phoneme;
+    use wav::AudioInfo;
+    use writers::DataDirOptions;
+
+    /// An utterance of `speaker` with `phonemes` phonemes in one second of audio.
+    fn utterance(speaker: &str, phonemes: usize) -> (DictEntry, TrainingEntry) {
+        let mut source = DictEntry::new_empty();
+        source.speaker = Some(String::from(speaker));
+        let entry = TrainingEntry {
+            id: format!("{}_{}", speaker, phonemes),
+            transcript: String::new(),
+            phonemes: (0..phonemes).map(|_| Phoneme::from_symbol("A", false)).collect(),
+            phoneme_lattice: None,
+            words: None,
+            audio_path: format!("{}/{}.wav", speaker, phonemes),
+            audio: Some(AudioInfo {
+                duration_seconds: 1.0,
+                sample_rate: 16000,
+                channels: 1,
+                bits_per_sample: 16,
+                codec: String::from("pcm_s16le")
+            }),
+            quality: None,
+            word_count: 0
+        };
+        (source, entry)
+    }
+
+    fn speakers() -> Speakers {
+        Speakers::new(Path::new("/corpus"), &DataDirOptions::default())
+    }
+
+    fn flagged(entries: &[(DictEntry, TrainingEntry)]) -> Vec<usize> {
+        find_outliers(entries, &speakers(), 3.5).iter().map(|v| v.index).collect()
+    }
+
+    #[test]
+    fn takes_the_median() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(&mut [7.0]), 7.0);
+    }
+
+    #[test]
+    fn measures_the_spread() {
+        // deviations 1.5, 0.5, 0.5 and 6.5 from the median 11.5
+        assert_eq!(spread(&[10.0, 11.0, 12.0, 18.0]), Some((11.5, 1.0)));
+        assert_eq!(spread(&[10.0, 12.0, 14.0]), Some((12.0, 2.0)));
+    }
+
+    #[test]
+    fn skips_too_few_utterances() {
+        assert_eq!(spread(&[]), None);
+        assert_eq!(spread(&[10.0]), None);
+        assert_eq!(spread(&[10.0, 20.0]), None);
+    }
+
+    #[test]
+    fn skips_utterances_without_spread() {
+        assert_eq!(spread(&[12.0, 12.0, 12.0]), None);
+        // most rates equal leave no deviation in the middle either
+        assert_eq!(spread(&[12.0, 12.0, 12.0, 30.0]), None);
+    }
+
+    #[test]
+    fn flags_an_obvious_outlier() {
+        let entries: Vec<_> = [10, 11, 12, 11, 40].iter().map(|v| utterance("spk", *v)).collect();
+        let outliers = find_outliers(&entries, &speakers(), 3.5);
+        assert_eq!(outliers.len(), 1);
+        assert_eq!(outliers[0].index, 4);
+        assert_eq!(outliers[0].audio_path, "spk/40.wav");
+        assert_eq!(outliers[0].speaker_median, 11.0);
+    }
+
+    #[test]
+    fn judges_each_speaker_apart() {
+        // a fast speaker is not an outlier of a slow one
+        let mut entries: Vec<_> = [10, 11, 12].iter().map(|v| utterance("slow", *v)).collect();
+        entries.extend([30, 31, 32, 60].iter().map(|v| utterance("fast", *v)));
+        assert_eq!(flagged(&entries), vec![6]);
+    }
+
+    #[test]
+    fn flags_nothing_without_spread() {
+        let entries: Vec<_> = [12, 12, 12, 12].iter().map(|v| utterance("spk", *v)).collect();
+        assert!(flagged(&entries).is_empty());
+    }
+
+    #[test]
+    fn flags_nothing_among_too_few_entries() {
+        assert!(flagged(&[]).is_empty());
+        let entries: Vec<_> = [10, 40].iter().map(|v| utterance("spk", *v)).collect();
+        assert!(flagged(&entries).is_empty());
+    }
+
+    #[test]
+    fn excludes_outliers_only_when_asked() {
+        let entries: Vec<_> = [10, 11, 12, 11, 40].iter().map(|v| utterance("spk", *v)).collect();
+        let report = std::env::temp_dir().join(format!("dict_walker_rate_check_{}.tsv", std::process::id()));
+        let options = RateCheckOptions { report: Some(report.clone()), threshold: 3.5, exclude: false };
+        assert!(check(&entries, &speakers(), &options).unwrap().is_empty());
+        let written = std::fs::read_to_string(&report).unwrap();
+        std::fs::remove_file(&report).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        assert!(written.lines().nth(1).unwrap().starts_with("spk\tspk/40.wav\t40.00\t11.00\t"));
+
+        let options = RateCheckOptions { exclude: true, ..RateCheckOptions::default() };
+        assert_eq!(check(&entries, &speakers(), &options).unwrap(), [4].iter().cloned().collect());
+    }
+}
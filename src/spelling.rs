@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use regex::NoExpand;
+use regex::Regex;
+use error::Error;
+use error::Result;
+
+/// One `[[rule]]` table of a spelling rule file, as written by the user.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    find: String,
+    replace: String,
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    word: bool,
+    comment: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<RuleDef>
+}
+
+/// A single correction. Literal rules replace `find` verbatim, regex rules may use `$name` groups in `replace`.
+/// `word` anchors the pattern on word boundaries on both sides.
+#[derive(Debug)]
+pub struct SpellingRule {
+    pattern: Regex,
+    replacement: String,
+    expand: bool,
+    pub comment: Option<String>
+}
+
+impl SpellingRule {
+    fn from_def(def: RuleDef) -> std::result::Result<SpellingRule, String> {
+        let mut pattern = if def.regex {
+            def.find.clone()
+        } else {
+            regex::escape(&def.find)
+        };
+        if def.word {
+            pattern = format!(r"\b(?:{})\b", pattern);
+        }
+
+        Ok(SpellingRule {
+            pattern: Regex::new(&pattern).map_err(|e| e.to_string())?,
+            replacement: def.replace,
+            expand: def.regex,
+            comment: def.comment
+        })
+    }
+
+    /// Applies the rule, returning `None` if it did not match.
+    pub fn apply(&self, str: &str) -> Option<String> {
+        if !self.pattern.is_match(str) {
+            return None;
+        }
+        let result = if self.expand {
+            self.pattern.replace_all(str, self.replacement.as_str())
+        } else {
+            self.pattern.replace_all(str, NoExpand(&self.replacement))
+        };
+        Some(result.into_owned())
+    }
+}
+
+/// Ordered list of spelling corrections loaded from a TOML rule file:
+///
+/// ```toml
+/// [[rule]]
+/// find = "_puslpais"
+/// replace = "_puslapis"
+/// comment = "misspelled marker"
+///
+/// [[rule]]
+/// find = '(?P<word>\w)_pauze'
+/// replace = '$word _pauze'
+/// regex = true
+/// ```
+///
+/// Rules run in file order, each on the output of the previous one.
+#[derive(Debug, Default)]
+pub struct SpellingRules {
+    pub rules: Vec<SpellingRule>
+}
+
+impl SpellingRules {
+    pub fn load(path: &Path) -> Result<SpellingRules> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut rules_str = String::new();
+        file.read_to_string(&mut rules_str).map_err(|e| Error::io(path, e))?;
+        SpellingRules::parse(path, &rules_str)
+    }
+
+    pub fn parse(path: &Path, rules_str: &str) -> Result<SpellingRules> {
+        let file: RuleFile = toml::from_str(rules_str)
+            .map_err(|e| Error::rule_file(path, e.to_string()))?;
+
+        let mut rules = Vec::new();
+        for (idx, def) in file.rule.into_iter().enumerate() {
+            let rule = SpellingRule::from_def(def)
+                .map_err(|e| Error::rule_file(path, format!("rule #{}: {}", idx + 1, e)))?;
+            rules.push(rule);
+        }
+        Ok(SpellingRules { rules })
+    }
+
+    pub fn apply(&self, str: String) -> String {
+        let mut result = str;
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if let Some(v) = rule.apply(&result) {
+                debug!(
+                    "Spelling rule #{} ({}) fired: \"{}\" -> \"{}\"",
+                    idx + 1,
+                    rule.comment.as_ref().unwrap_or(&rule.replacement),
+                    result,
+                    v
+                );
+                result = v;
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use regex::Regex;
+use error::Error;
+use error::Result;
+
+/// The fields of a written `TrainingEntry` that the report needs.
+#[derive(Deserialize)]
+struct OutputRecord {
+    transcript: String,
+    phonemes: String
+}
+
+/// Summary of an existing walker output file.
+#[derive(Debug, Default, Serialize)]
+pub struct DatasetStats {
+    pub entries: usize,
+    /// Lexical words, markers and mid-word pauses not counted.
+    pub words: usize,
+    pub unique_words: usize,
+    /// Markers and mid-word pauses, by tag.
+    pub markers: usize,
+    pub marker_counts: BTreeMap<String, usize>,
+    pub phonemes: usize,
+    pub unresolved_words: usize,
+    pub phoneme_counts: BTreeMap<String, usize>
+}
+
+impl DatasetStats {
+    /// Reads a walker output file, either a JSON array or JSON Lines.
+    pub fn from_output(path: &Path) -> Result<DatasetStats> {
+        let mut reader = BufReader::new(File::open(path).map_err(|e| Error::io(path, e))?);
+        let is_array = loop {
+            let buffer = reader.fill_buf().map_err(|e| Error::io(path, e))?;
+            match buffer.iter().position(|v| !v.is_ascii_whitespace()) {
+                Some(idx) => break buffer[idx] == b'[',
+                None if buffer.is_empty() => break false,
+                None => {
+                    let len = buffer.len();
+                    reader.consume(len);
+                }
+            }
+        };
+
+        let records: Vec<OutputRecord> = if is_array {
+            serde_json::from_reader(reader)
+                .map_err(|e| Error::decode(path, e.to_string()))?
+        } else {
+            serde_json::Deserializer::from_reader(reader)
+                .into_iter()
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| Error::decode(path, e.to_string()))?
+        };
+        Ok(DatasetStats::from_records(&records))
+    }
+
+    fn from_records(records: &[OutputRecord]) -> DatasetStats {
+        lazy_static! {
+            static ref PHONEME_RE: Regex = Regex::new(r"[\[{](?P<symbol>[^\]}]+)[\]}]").unwrap();
+            // transcripts are written with markers as bracketed tags, glued to the words around mid-word pauses
+            static ref MARKER_RE: Regex = Regex::new(r"\[(?P<tag>[^\]]+)\]").unwrap();
+        }
+
+        let mut stats = DatasetStats::default();
+        let mut unique: HashSet<&str> = HashSet::new();
+        for record in records {
+            stats.entries += 1;
+            for chunk in record.transcript.split_whitespace() {
+                for caps in MARKER_RE.captures_iter(chunk) {
+                    stats.markers += 1;
+                    *stats.marker_counts.entry(caps["tag"].to_string()).or_insert(0) += 1;
+                }
+                for word in MARKER_RE.split(chunk).filter(|v| !v.is_empty()) {
+                    stats.words += 1;
+                    unique.insert(word);
+                }
+            }
+            for caps in PHONEME_RE.captures_iter(&record.phonemes) {
+                let symbol = &caps["symbol"];
+                if symbol.starts_with("ERR-") {
+                    stats.unresolved_words += 1;
+                } else {
+                    stats.phonemes += 1;
+                    *stats.phoneme_counts.entry(symbol.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+        stats.unique_words = unique.len();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(transcript: &str, phonemes: &str) -> OutputRecord {
+        OutputRecord { transcript: String::from(transcript), phonemes: String::from(phonemes) }
+    }
+
+    #[test]
+    fn counts_words_apart_from_markers() {
+        let stats = DatasetStats::from_records(&[
+            record("labas [PAUSE] rytas", "[L][A][B][A][S]{PAUSE}[R][I_][T][A][S]"),
+            record("diena[MIDWORDPAUSE]x [INHALE] labas", "")
+        ]);
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.words, 5);
+        assert_eq!(stats.unique_words, 4);
+        assert_eq!(stats.markers, 3);
+        assert_eq!(stats.marker_counts.get("PAUSE"), Some(&1));
+        assert_eq!(stats.marker_counts.get("MIDWORDPAUSE"), Some(&1));
+        assert_eq!(stats.marker_counts.get("INHALE"), Some(&1));
+    }
+
+    #[test]
+    fn counts_phonemes() {
+        let stats = DatasetStats::from_records(&[record("labas xyz", "[L][A][B][A][S]{PAUSE}[ERR-xyz]")]);
+        assert_eq!((stats.phonemes, stats.unresolved_words), (6, 1));
+        assert_eq!(stats.phoneme_counts.get("A"), Some(&2));
+    }
+}
@@ -1,19 +1,79 @@
 use phonemes::Phoneme;
+use phonemes::LAST_PHONETIC_ORDINAL;
 use dict_entry::DictEntry;
+use path_encoding::PathEncoding;
 use regex::Regex;
 use phoneme_resolvers::PhonemeResolver;
-use std::str::FromStr;
+use markers::MarkerTable;
+use transcript::Token;
+use transcript::Transcript;
+use transcript::Word;
 use serde::Serializer;
+use std::str::FromStr;
+use std::path::Path;
+use error::Error;
+use error::Result;
+use spelling::SpellingRules;
+use wav::AudioInfo;
+use audio_quality::AudioQuality;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TrainingEntry {
+    /// Unique ID of the entry, see `DictEntry::id`.
+    pub id: String,
     pub transcript: String,
     #[serde(serialize_with = "serialize_phoneme_vec")]
     pub phonemes: Vec<Phoneme>,
-    pub audio_path: String
+    /// Every pronunciation of every word, only filled with `VariantMode::All`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phoneme_lattice: Option<String>,
+    /// Per word resolution records, only filled when `EntryOptions::provenance` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<Vec<WordRecord>>,
+    /// The audio file as `EntryOptions::path_encoding` writes it.
+    pub audio_path: String,
+    /// WAV header data, only filled when the audio was probed.
+    #[serde(flatten)]
+    pub audio: Option<AudioInfo>,
+    /// Sample metrics, only filled when the audio was analyzed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<AudioQuality>,
+    /// Spoken words of the transcript, markers and mid-word pauses not counted.
+    #[serde(skip)]
+    pub word_count: usize
+}
+
+/// Where the pronunciation of a single transcript word (or marker) came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct WordRecord {
+    /// The word as it was looked up: lowercased, or the bracketed tag of a marker.
+    pub word: String,
+    /// The preferred pronunciation.
+    #[serde(serialize_with = "serialize_phoneme_vec")]
+    pub phonemes: Vec<Phoneme>,
+    /// `PhonemeResolver::name` of the resolver which answered.
+    pub resolver: String,
+    /// Every pronunciation the resolver knows, preferred one first.
+    #[serde(skip)]
+    pub variants: Vec<Vec<Phoneme>>
+}
+
+/// Resolved transcript: every segment holds the alternative pronunciations of one token (or a single pause
+/// separator), `words` tells which resolver answered each word and marker.
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub segments: Vec<Vec<Vec<Phoneme>>>,
+    pub words: Vec<WordRecord>
+}
+
+impl Resolution {
+    /// The preferred pronunciation of the whole transcript.
+    pub fn phonemes(&self) -> Vec<Phoneme> {
+        self.segments.iter().flat_map(|v| v[0].iter().cloned()).collect()
+    }
 }
 
-fn serialize_phoneme_vec<S>(vec: &Vec<Phoneme>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+fn serialize_phoneme_vec<S>(vec: &[Phoneme], serializer: S) -> std::result::Result<S::Ok, S::Error> where S: Serializer {
     let mut str = String::new();
     for ph in vec {
         str.push_str(&ph.to_string());
@@ -21,6 +81,45 @@ fn serialize_phoneme_vec<S>(vec: &Vec<Phoneme>, serializer: S) -> Result<S::Ok,
     serializer.serialize_str(&str)
 }
 
+/// Which pronunciations of words with several dictionary variants end up in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VariantMode {
+    /// Only the preferred pronunciation, in `phonemes`.
+    #[default]
+    First,
+    /// The preferred pronunciation in `phonemes` and all of them in `phoneme_lattice`.
+    All
+}
+
+impl FromStr for VariantMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        match s {
+            "first" => Ok(VariantMode::First),
+            "all" => Ok(VariantMode::All),
+            _ => Err(format!("\"{}\" is not a valid variant mode, expected \"first\" or \"all\".", s))
+        }
+    }
+}
+
+/// Per entry output switches.
+#[derive(Debug, Clone, Default)]
+pub struct EntryOptions {
+    pub variants: VariantMode,
+    /// Record which resolver produced every word.
+    pub provenance: bool,
+    /// How `audio_path` is written when it is not UTF-8.
+    pub path_encoding: PathEncoding
+}
+
+/// Corpus specific transcript clean-up rules.
+#[derive(Debug, Default)]
+pub struct TranscriptRules {
+    pub spelling: SpellingRules,
+    pub markers: MarkerTable
+}
+
 impl TrainingEntry {
     //TODO: perform some postprocessing on the transcript
     // --encoding errors:
@@ -29,30 +128,10 @@ impl TrainingEntry {
     // remove \u{1f}
 
     // --errors:
-    // _centrai centrai - error in transcript, included twice
-    // indais _dais - fix detached accent
-    // _is kvepimas - fix misspelled marker
-    // _puslpais - spelling error, fix to _puslapis
-    // _dutys - spelling error, fix to _durys
-
-    // rename markers:
-    // _pauze
-    // _tyla
-    // _ikvepimas
-    // _iskvepimas
-    // _nurijimas
-    // _cepsejimas
-    // _kede
-    // _pilvas
-    // _garsas
-    // _puslapis
-    // _durys
-    // _eh
-    // - //middle word pauses
-
-    // --accents:
-    // _ prefix
-    // _[A-Za-z] postfix
+    // corpus specific, loaded from a spelling rule file (see rules/liepa_spelling.toml)
+
+    // --markers, accents and mid-word pauses:
+    // tokenized by transcript::Transcript using the marker definition file (see rules/markers.toml)
 
     // --post cleaning ops:
     // remove \r \n and \t
@@ -66,99 +145,200 @@ impl TrainingEntry {
             .replace("\u{1f}", "")
     }
 
-    fn fix_spelling_errors(str: String) -> String {
-        str.replace("_centrai centrai", "centrai")
-            .replace("indais _dais", "indais_dais")
-            .replace("_is kvepimas", "_iskvepimas")
-            .replace("_puslpais", "_puslapis")
-            .replace("_dutys", "_durys")
-            .replace("Simono-Petro", "Simono Petro")
-            .replace("Achemenidu", "Achemenidų")
-            .replace("windous", "vindous")
-            .replace("wort", "vort")
-            .replace("word", "vord")
-            .replace("powerpoint", "poverpoint")
-            .replace("da˛niausiai", "dažniausiai")
-            //.replace("_ikvepimasvykdyk", "_ikvepimas vykdyk")
-            //.replace("laiškas_ikvepimas", "laiškas _ikvepimas")
-            //.replace("mastelį_pauze", "mastelį _pauze")
-            //.replace("mastelį_tyla", "mastelį _tyla")
-    }
-
-    fn process_markers(str: String) -> String {
-        str.replace("_pauze", "[PAUSE]")
-            .replace("_tyla", "[PAUSE]")
-            .replace("_ikvepimas", "[INHALE]")
-            .replace("_iskvepimas", "[EXHALE]")
-            .replace("_nurijimas", "[SWALLOW]")
-            .replace("_cepsejimas", "[SMACK]")
-            .replace("_kede", "[CHAIR]")
-            .replace("_pilvas", "[STOMACH]")
-            .replace("_garsas", "[NOISE]")
-            .replace("_puslapis", "[PAGE]")
-            .replace("_durys", "[DOOR]")
-            .replace("_eh", "[EH]")
-            .replace("-", "[MIDWORDPAUSE]")
-    }
-
-    fn process_accents(str: String) -> String {
-        lazy_static! {
-            static ref postfix_accent_re: Regex = Regex::new(r"(?P<last>[^ ])(?P<accent>_[^ ]+)").unwrap();
-        }
-        postfix_accent_re.replace_all(&str, "$last")
-            .replace("_", "")
-    }
-
     fn perform_postprocessing(str: String) -> String {
         lazy_static! {
-            static ref multi_space_re: Regex = Regex::new(r" {2,}").unwrap();
+            static ref MULTI_SPACE_RE: Regex = Regex::new(r" {2,}").unwrap();
         }
-        String::from(multi_space_re.replace_all(str.replace("\r", " ")
+        String::from(MULTI_SPACE_RE.replace_all(str.replace("\r", " ")
             .replace("\n", " ")
             .replace("\t", " ")
             .trim(), " "))
     }
 
-    fn convert_to_phonemes(str: &str, resolvers: &Vec<Box<PhonemeResolver>>) -> Vec<Phoneme> {
-        let mut result: Vec<Phoneme> = Vec::new();
-        let words: Vec<&str> = str.split_whitespace().collect();
+    /// Runs a single word through the resolver chain, returning the result of the first resolver that knows it.
+    pub fn resolve_word(word: &str, resolvers: &[Box<dyn PhonemeResolver>]) -> Option<Vec<Phoneme>> {
+        TrainingEntry::resolve_word_variants(word, resolvers)
+            .and_then(|mut v| if v.is_empty() { None } else { Some(v.remove(0)) })
+    }
 
-        for i in 0..words.len() {
-            let word = if words[i].starts_with("[") {
-                words[i].to_string()
-            } else {
-                words[i].to_lowercase()
-            };
+    /// Like `resolve_word`, but returns every pronunciation the answering resolver knows.
+    pub fn resolve_word_variants(word: &str, resolvers: &[Box<dyn PhonemeResolver>]) -> Option<Vec<Vec<Phoneme>>> {
+        TrainingEntry::resolve_word_sourced(word, resolvers).map(|(_, v)| v)
+    }
+
+    /// Like `resolve_word_variants`, also returning the name of the answering resolver.
+    pub fn resolve_word_sourced(word: &str, resolvers: &[Box<dyn PhonemeResolver>]) -> Option<(String, Vec<Vec<Phoneme>>)> {
+        let word = TrainingEntry::normalize_word(word);
+        resolvers.iter()
+            .filter_map(|resolver| resolver.resolve_all(&word).map(|v| (resolver.name(), v)))
+            .next()
+    }
+
+    /// The form of a word the resolvers are asked for: lowercased, bracketed markers as they are.
+    pub fn normalize_word(word: &str) -> String {
+        if word.starts_with('[') {
+            word.to_string()
+        } else {
+            word.to_lowercase()
+        }
+    }
+
+    /// Marks the first vowel of the stressed vowel group as accented.
+    fn mark_stress(mut phonemes: Vec<Phoneme>, word: &Word) -> Vec<Phoneme> {
+        let stress = match word.stress {
+            Some(v) => v,
+            None => return phonemes
+        };
+
+        let mut group = 0;
+        let mut in_vowel = false;
+        for ph in phonemes.iter_mut() {
+            let vowel = ph.is_vowel();
+            if vowel && !in_vowel {
+                if group == stress {
+                    ph.accented = true;
+                    return phonemes;
+                }
+                group += 1;
+            }
+            in_vowel = vowel;
+        }
+
+        debug!("Word \"{}\" has no vowel group #{} to stress", word.text, stress);
+        phonemes
+    }
+
+    /// Resolves every token, separating words with pauses.
+    /// Fails on the first token no resolver of the chain knows, `origin` names the transcript in the error.
+    pub fn convert_to_variants(transcript: &Transcript, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], origin: &Path) -> Result<Resolution> {
+        let mut result = Resolution::default();
+        let mut previous: Option<&Token> = None;
 
-            for resolver in resolvers.iter() {
-                match resolver.resolve(&word) {
-                    Some(mut v) => {
-                        v.drain(0..)
-                            .for_each(|v| result.push(v));
-                        if i != words.len()-1 {
-                            result.push(Phoneme::from_str(" ").unwrap());
-                        }
-                        break;
-                    },
-                    None => continue
+        for token in transcript.tokens.iter() {
+            let variants = match token {
+                Token::MidWordPause => vec![vec![rules.markers.midword_pause()]],
+                _ => {
+                    // words print as their text, markers as their bracketed tag
+                    let word = token.to_string();
+                    let (resolver, variants) = match TrainingEntry::resolve_word_sourced(&word, resolvers) {
+                        Some((name, v)) if !v.is_empty() => (name, v),
+                        _ => return Err(Error::UnresolvedWord { path: origin.to_path_buf(), word })
+                    };
+                    let variants: Vec<Vec<Phoneme>> = match token {
+                        Token::Word(w) => variants.into_iter().map(|ph| TrainingEntry::mark_stress(ph, w)).collect(),
+                        _ => variants
+                    };
+                    result.words.push(WordRecord {
+                        word: TrainingEntry::normalize_word(&word),
+                        phonemes: variants[0].clone(),
+                        resolver,
+                        variants: variants.clone()
+                    });
+                    variants
                 }
+            };
+
+            match (previous, token) {
+                (None, _) | (Some(Token::MidWordPause), _) | (_, Token::MidWordPause) => (),
+                _ => result.segments.push(vec![vec![rules.markers.pause()]])
             }
+            result.segments.push(variants);
+            previous = Some(token);
         }
 
-        result
+        Ok(result)
+    }
+
+    /// Resolves every token with its preferred pronunciation, separating words with pauses.
+    pub fn convert_to_phonemes(transcript: &Transcript, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], origin: &Path) -> Result<Vec<Phoneme>> {
+        Ok(TrainingEntry::convert_to_variants(transcript, rules, resolvers, origin)?.phonemes())
+    }
+
+    /// Renders segments as a phoneme string where words with several pronunciations become `(A|B)` groups.
+    pub fn render_lattice(segments: &[Vec<Vec<Phoneme>>]) -> String {
+        let render = |phonemes: &Vec<Phoneme>| phonemes.iter().map(|ph| ph.to_string()).collect::<String>();
+        let mut str = String::new();
+        for variants in segments {
+            if variants.len() == 1 {
+                str.push_str(&render(&variants[0]));
+            } else {
+                str.push('(');
+                str.push_str(&variants.iter().map(&render).collect::<Vec<String>>().join("|"));
+                str.push(')');
+            }
+        }
+        str
     }
 
-    pub fn construct(de: DictEntry, resolvers: &Vec<Box<PhonemeResolver>>) -> Self {
-        let mut t = TrainingEntry::fix_encoding_errors(de.transcript);
-        t = TrainingEntry::fix_spelling_errors(t);
-        t = TrainingEntry::process_markers(t);
-        t = TrainingEntry::process_accents(t);
+    /// Applies the whole transcript clean-up chain (encoding, spelling, whitespace) and splits the result into tokens.
+    pub fn tokenize(transcript: String, rules: &TranscriptRules) -> Transcript {
+        let mut t = TrainingEntry::fix_encoding_errors(transcript);
+        t = rules.spelling.apply(t);
         t = TrainingEntry::perform_postprocessing(t);
+        Transcript::tokenize(&t, &rules.markers)
+    }
 
-        TrainingEntry {
-            phonemes: TrainingEntry::convert_to_phonemes(&t, resolvers),
-            audio_path: de.audio_path,
-            transcript: t
+    pub fn construct(de: DictEntry, rules: &TranscriptRules, resolvers: &[Box<dyn PhonemeResolver>], options: &EntryOptions) -> Result<Self> {
+        let transcript = TrainingEntry::tokenize(de.transcript, rules);
+        for chunk in transcript.glued.iter() {
+            warn!("Detached marker glued to a word in \"{}\" ({:?})", chunk, de.transcript_path);
         }
+
+        let resolution = TrainingEntry::convert_to_variants(&transcript, rules, resolvers, &de.transcript_path)?;
+        let phoneme_lattice = match options.variants {
+            VariantMode::First => None,
+            VariantMode::All => Some(TrainingEntry::render_lattice(&resolution.segments))
+        };
+
+        Ok(TrainingEntry {
+            id: de.id,
+            phonemes: resolution.phonemes(),
+            phoneme_lattice,
+            words: if options.provenance { Some(resolution.words) } else { None },
+            audio_path: options.path_encoding.encode(&de.audio_path),
+            audio: de.audio,
+            quality: de.quality,
+            word_count: transcript.words().count(),
+            transcript: transcript.to_string()
+        })
+    }
+
+    /// Phonemes of the phonetic inventory, markers and pauses not counted. Unresolved words count as one.
+    pub fn phoneme_count(&self) -> usize {
+        self.phonemes.iter().filter(|v| v.ordinal <= LAST_PHONETIC_ORDINAL).count()
     }
-}
\ No newline at end of file
+
+    /// Phonemes per second of audio, known only for probed entries.
+    pub fn phoneme_rate(&self) -> Option<f64> {
+        match self.audio {
+            Some(ref audio) if audio.duration_seconds > 0.0 => Some(self.phoneme_count() as f64 / audio.duration_seconds),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accented(symbols: &str, text: &str, accent: &str) -> Vec<bool> {
+        let phonemes: Vec<Phoneme> = symbols.split(' ').map(|v| Phoneme::from_str(v).unwrap()).collect();
+        let mut word = Word::new(text);
+        word.set_accent(accent);
+        TrainingEntry::mark_stress(phonemes, &word).iter().map(|v| v.accented).collect()
+    }
+
+    #[test]
+    fn accents_the_first_vowel_of_the_group() {
+        assert_eq!(accented("L A U K A I", "laukai", "ukai"), vec![false, true, false, false, false, false]);
+        assert_eq!(accented("L A U K A I", "laukai", "kai"), vec![false, false, false, false, true, false]);
+        assert_eq!(accented("D I E N O_ S", "dienos", "nos"), vec![false, false, false, false, true, false]);
+    }
+
+    #[test]
+    fn leaves_words_without_stress() {
+        assert_eq!(accented("L A B A S", "labas", "x"), vec![false; 5]);
+        // more syllables in the spelling than vowel groups in the pronunciation
+        assert_eq!(accented("L A B S", "labas", "bas"), vec![false; 4]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt;
+use std::fmt::Formatter;
+use markers::MarkerTable;
+use markers::MIDWORDPAUSE;
+
+/// A spoken word with its accent annotation (the `_X` postfix), if the annotator wrote one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    pub accent: Option<String>,
+    /// Index of the stressed syllable (vowel group) derived from `accent`.
+    pub stress: Option<usize>
+}
+
+const VOWELS: &str = "aąeęėiįyouųū";
+
+impl Word {
+    pub fn new(text: &str) -> Word {
+        Word { text: text.to_string(), accent: None, stress: None }
+    }
+
+    /// Records the accent annotation. The annotation is the tail of the word starting at the stressed
+    /// syllable (`indais_dais`), so the stress lands on the first vowel group at or after its last occurrence.
+    pub fn set_accent(&mut self, accent: &str) {
+        self.accent = Some(accent.to_string());
+        self.stress = Word::stressed_syllable(&self.text, accent);
+        if self.stress.is_none() {
+            debug!("Cannot place accent \"{}\" in word \"{}\"", accent, self.text);
+        }
+    }
+
+    /// Lowercase and without diacritics, annotators often leave them out of the accent (`kėdė_kede`).
+    fn fold(text: &str) -> String {
+        text.to_lowercase().chars()
+            .map(|c| match c {
+                'ą' => 'a',
+                'č' => 'c',
+                'ę' | 'ė' => 'e',
+                'į' => 'i',
+                'š' => 's',
+                'ų' | 'ū' => 'u',
+                'ž' => 'z',
+                c => c
+            })
+            .collect()
+    }
+
+    /// Whether an annotation can be the accent of this word, i.e. some tail of it.
+    fn takes_accent(&self, accent: &str) -> bool {
+        self.accent.is_none() && Word::fold(&self.text).contains(&Word::fold(accent))
+    }
+
+    fn stressed_syllable(text: &str, accent: &str) -> Option<usize> {
+        let text = Word::fold(text);
+        let position = text.rfind(&Word::fold(accent))?;
+
+        let mut syllable = 0;
+        let mut in_vowel = false;
+        for (idx, c) in text.char_indices() {
+            let vowel = VOWELS.contains(c);
+            if vowel && idx >= position {
+                return Some(if in_vowel { syllable - 1 } else { syllable });
+            }
+            if vowel && !in_vowel {
+                syllable += 1;
+            }
+            in_vowel = vowel;
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    /// A non-speech marker, by its canonical tag.
+    Marker(String),
+    /// A pause inside a word, e.g. the hyphen of `die-na`. Hyphens joining capitalized parts (`Simono-Petro`) are
+    /// compounds and stay in the word.
+    MidWordPause
+}
+
+/// A transcript split into tokens. Consecutive tokens are separate words unless one of them is a `MidWordPause`.
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    pub tokens: Vec<Token>,
+    /// Markers which were written glued to a word (`mastelį_pauze`) and had to be detached.
+    pub glued: Vec<String>
+}
+
+impl Transcript {
+    /// Tokenizes a transcript whose whitespace has already been normalized.
+    pub fn tokenize(str: &str, markers: &MarkerTable) -> Transcript {
+        let mut transcript = Transcript::default();
+        let midword_spellings: Vec<&str> = match markers.by_tag(MIDWORDPAUSE) {
+            Some(v) => v.spellings.iter().map(|s| s.as_str()).collect(),
+            None => Vec::new()
+        };
+
+        for chunk in str.split_whitespace() {
+            if let Some(marker) = markers.by_spelling(chunk).or_else(|| markers.by_bracketed(chunk)) {
+                transcript.tokens.push(Token::Marker(marker.tag.clone()));
+                continue;
+            }
+
+            for (idx, piece) in Transcript::split_midword(chunk, &midword_spellings).iter().enumerate() {
+                if idx != 0 {
+                    transcript.tokens.push(Token::MidWordPause);
+                }
+                transcript.tokenize_piece(piece, chunk, markers);
+            }
+        }
+
+        transcript
+    }
+
+    /// Splits a chunk at its mid-word pauses. Annotators mark pauses inside lowercase words, while Lithuanian only
+    /// hyphenates compounds of names, so a pause spelling between two capitalized parts is left in place.
+    fn split_midword<'a>(chunk: &'a str, spellings: &[&str]) -> Vec<&'a str> {
+        let capitalized = |v: &str| v.chars().next().map(char::is_uppercase).unwrap_or(false);
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for (idx, _) in chunk.char_indices() {
+            if idx < start {
+                continue;
+            }
+            let spelling = match spellings.iter().find(|s| chunk[idx..].starts_with(*s)) {
+                Some(v) => v,
+                None => continue
+            };
+            let rest = &chunk[idx + spelling.len()..];
+            if capitalized(&chunk[start..idx]) && capitalized(rest) {
+                continue;
+            }
+            pieces.push(&chunk[start..idx]);
+            start = idx + spelling.len();
+        }
+        pieces.push(&chunk[start..]);
+        pieces
+    }
+
+    /// Tokenizes a piece of a chunk free of whitespace and mid-word pauses: `word`, `word_accent`, `_marker`,
+    /// or combinations of those glued together.
+    fn tokenize_piece(&mut self, piece: &str, chunk: &str, markers: &MarkerTable) {
+        let mut parts = piece.split('_');
+        let head = parts.next().unwrap_or("");
+        let mut has_word = false;
+        if !head.is_empty() {
+            self.tokens.push(Token::Word(Word::new(head)));
+            has_word = true;
+        }
+
+        for part in parts.filter(|v| !v.is_empty()) {
+            let spelling = format!("_{}", part);
+            // an accent may be spelled like a marker (`durys_durys`), it is one if the word ends with it
+            let accent = match self.tokens.last() {
+                Some(Token::Word(word)) if has_word => word.takes_accent(part),
+                _ => false
+            };
+            if let Some(marker) = markers.by_spelling(&spelling).filter(|_| !accent) {
+                if has_word {
+                    self.glued.push(chunk.to_string());
+                }
+                self.tokens.push(Token::Marker(marker.tag.clone()));
+                continue;
+            }
+
+            // a marker with the next word glued to its end, e.g. `_ikvepimasvykdyk`; after a word this is
+            // an accent annotation instead (`tylaus_tylaus`)
+            let prefixed = markers.markers().iter()
+                .flat_map(|m| m.spellings.iter().map(move |s| (m, s)))
+                .filter(|&(_, s)| !has_word && s.starts_with('_') && spelling.starts_with(s.as_str()))
+                .max_by_key(|&(_, s)| s.len());
+            if let Some((marker, s)) = prefixed {
+                self.glued.push(chunk.to_string());
+                self.tokens.push(Token::Marker(marker.tag.clone()));
+                self.tokens.push(Token::Word(Word::new(&spelling[s.len()..])));
+                has_word = true;
+                continue;
+            }
+
+            match self.tokens.last_mut() {
+                Some(Token::Word(ref mut word)) if has_word && word.accent.is_none() => {
+                    word.set_accent(part);
+                },
+                _ if !has_word => {
+                    debug!("Dropping the underscore of \"{}\" in \"{}\"", spelling, chunk);
+                    self.tokens.push(Token::Word(Word::new(part)));
+                    has_word = true;
+                },
+                _ => debug!("Ignoring annotation \"{}\" in \"{}\"", spelling, chunk)
+            }
+        }
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &Word> {
+        self.tokens.iter().filter_map(|v| match v {
+            Token::Word(w) => Some(w),
+            _ => None
+        })
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{}", w.text),
+            Token::Marker(tag) => write!(f, "[{}]", tag),
+            Token::MidWordPause => write!(f, "[{}]", MIDWORDPAUSE)
+        }
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut previous: Option<&Token> = None;
+        for token in self.tokens.iter() {
+            let glued = matches!(
+                (previous, token),
+                (None, _) | (Some(Token::MidWordPause), _) | (_, Token::MidWordPause)
+            );
+            if !glued {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", token)?;
+            previous = Some(token);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(str: &str) -> Transcript {
+        Transcript::tokenize(str, &MarkerTable::default())
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(Word::new(text))
+    }
+
+    fn accented(text: &str, accent: &str) -> Token {
+        let mut word = Word::new(text);
+        word.set_accent(accent);
+        Token::Word(word)
+    }
+
+    fn marker(tag: &str) -> Token {
+        Token::Marker(String::from(tag))
+    }
+
+    #[test]
+    fn splits_words_and_markers() {
+        let transcript = tokenize("labas _pauze rytas [INHALE]");
+        assert_eq!(transcript.tokens, vec![word("labas"), marker("PAUSE"), word("rytas"), marker("INHALE")]);
+        assert!(transcript.glued.is_empty());
+    }
+
+    #[test]
+    fn splits_midword_pauses() {
+        // hyphens in lowercase words are where the speaker paused
+        assert_eq!(tokenize("diena-x").tokens, vec![word("diena"), Token::MidWordPause, word("x")]);
+        assert_eq!(tokenize("a-b-c").tokens, vec![word("a"), Token::MidWordPause, word("b"), Token::MidWordPause, word("c")]);
+        // a pause at the start of a capitalized word, or cutting one short
+        assert_eq!(tokenize("Die-na").tokens, vec![word("Die"), Token::MidWordPause, word("na")]);
+        assert_eq!(tokenize("Simo-").tokens, vec![word("Simo"), Token::MidWordPause]);
+        // a marker after the pause is no glued one
+        let transcript = tokenize("labas-_pauze");
+        assert_eq!(transcript.tokens, vec![word("labas"), Token::MidWordPause, marker("PAUSE")]);
+        assert!(transcript.glued.is_empty());
+    }
+
+    #[test]
+    fn keeps_compounds_of_names() {
+        assert_eq!(tokenize("Simono-Petro").tokens, vec![word("Simono-Petro")]);
+        assert_eq!(tokenize("Simono-Petro-Jono").tokens, vec![word("Simono-Petro-Jono")]);
+        assert_eq!(tokenize("Simono-Petro-x").tokens, vec![word("Simono-Petro"), Token::MidWordPause, word("x")]);
+        assert_eq!(tokenize("Simono-Petro_tro").tokens, vec![accented("Simono-Petro", "tro")]);
+        assert_eq!(tokenize("Simono-Petro").to_string(), "Simono-Petro");
+    }
+
+    #[test]
+    fn splits_accent_annotations() {
+        assert_eq!(tokenize("indais_dais").tokens, vec![accented("indais", "dais")]);
+        // only the first annotation counts
+        assert_eq!(tokenize("indais_dais_ais").tokens, vec![accented("indais", "dais")]);
+        // a leading underscore without a marker is dropped
+        assert_eq!(tokenize("_labas").tokens, vec![word("labas")]);
+    }
+
+    #[test]
+    fn detaches_glued_markers() {
+        let transcript = tokenize("mastelį_pauze");
+        assert_eq!(transcript.tokens, vec![word("mastelį"), marker("PAUSE")]);
+        assert_eq!(transcript.glued, vec!["mastelį_pauze"]);
+
+        let transcript = tokenize("_ikvepimasvykdyk");
+        assert_eq!(transcript.tokens, vec![marker("INHALE"), word("vykdyk")]);
+        assert_eq!(transcript.glued, vec!["_ikvepimasvykdyk"]);
+
+        // the longest spelling wins, and the word after it may carry an accent
+        let transcript = tokenize("_iskvepimaslabas_bas");
+        assert_eq!(transcript.tokens, vec![marker("EXHALE"), accented("labas", "bas")]);
+    }
+
+    #[test]
+    fn keeps_accents_spelled_like_markers() {
+        assert_eq!(tokenize("durys_durys").tokens, vec![accented("durys", "durys")]);
+        assert_eq!(tokenize("pilvas_pilvas").tokens, vec![accented("pilvas", "pilvas")]);
+        assert_eq!(tokenize("kėdė_kede").tokens, vec![accented("kėdė", "kede")]);
+        assert_eq!(tokenize("mokytojas_ehas").tokens, vec![accented("mokytojas", "ehas")]);
+        assert_eq!(tokenize("tylaus_tylaus").tokens, vec![accented("tylaus", "tylaus")]);
+        assert!(tokenize("durys_durys tylaus_tylaus").glued.is_empty());
+        // a marker the word does not end with is still a glued one, also after an accent
+        let transcript = tokenize("durys_durys_pauze");
+        assert_eq!(transcript.tokens, vec![accented("durys", "durys"), marker("PAUSE")]);
+        assert_eq!(transcript.glued, vec!["durys_durys_pauze"]);
+    }
+
+    #[test]
+    fn prints_tokens() {
+        assert_eq!(tokenize("labas-x _pauze mastelį_pauze").to_string(), "labas[MIDWORDPAUSE]x [PAUSE] mastelį [PAUSE]");
+    }
+
+    #[test]
+    fn stresses_the_annotated_syllable() {
+        assert_eq!(Word::stressed_syllable("indais", "dais"), Some(1));
+        assert_eq!(Word::stressed_syllable("dienos", "nos"), Some(1));
+        assert_eq!(Word::stressed_syllable("Dienos", "NOS"), Some(1));
+        assert_eq!(Word::stressed_syllable("žąsis", "sis"), Some(1));
+        assert_eq!(Word::stressed_syllable("kėdė", "dė"), Some(1));
+        assert_eq!(Word::stressed_syllable("kėdė", "de"), Some(1));
+        // the last occurrence of the annotation counts
+        assert_eq!(Word::stressed_syllable("dadada", "da"), Some(2));
+    }
+
+    #[test]
+    fn stresses_whole_vowel_groups() {
+        // an annotation starting inside a vowel group stresses the whole group
+        assert_eq!(Word::stressed_syllable("laukai", "ukai"), Some(0));
+        assert_eq!(Word::stressed_syllable("laukai", "kai"), Some(1));
+        assert_eq!(Word::stressed_syllable("dienos", "enos"), Some(0));
+        assert_eq!(Word::stressed_syllable("ieškoti", "ieškoti"), Some(0));
+    }
+
+    #[test]
+    fn leaves_unplaceable_accents() {
+        assert_eq!(Word::stressed_syllable("labas", "x"), None);
+        assert_eq!(Word::stressed_syllable("labas", "s"), None);
+        let mut word = Word::new("labas");
+        word.set_accent("x");
+        assert_eq!((word.accent, word.stress), (Some(String::from("x")), None));
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fs::read_dir;
+use std::fs::symlink_metadata;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use regex::Regex;
+use dict_entry::DictEntry;
+use path_encoding;
+use error::Error;
+use error::Result;
+
+/// Name of the files listing paths to leave out of the walk, honoured in every directory.
+pub const IGNORE_FILE: &str = ".dictwalkerignore";
+
+/// Shell style pattern for paths relative to the dictionary, or to the directory of an ignore file.
+/// `*` and `?` stay within a path component, `**` spans any number of them, `[...]` is a character class.
+/// Patterns without an inner `/` match the file name at any depth, a trailing `/` only matches directories.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    source: String,
+    regex: Regex,
+    dir_only: bool,
+    /// `!` in ignore files, bringing back what an earlier pattern left out.
+    negated: bool
+}
+
+impl Glob {
+    fn glob_regex(glob: &str) -> std::result::Result<String, String> {
+        let mut regex = String::from(if glob.contains('/') { "^" } else { "^(?:.*/)?" });
+        let glob = glob.trim_start_matches('/');
+        let chars: Vec<char> = glob.chars().collect();
+        let mut idx = 0;
+        while idx < chars.len() {
+            match chars[idx] {
+                '*' if chars.get(idx + 1) == Some(&'*') => {
+                    // `**/` as a whole component also matches no directory at all
+                    if (idx == 0 || chars[idx - 1] == '/') && chars.get(idx + 2) == Some(&'/') {
+                        regex.push_str("(?:.*/)?");
+                        idx += 3;
+                    } else {
+                        regex.push_str(".*");
+                        idx += 2;
+                    }
+                },
+                '*' => {
+                    regex.push_str("[^/]*");
+                    idx += 1;
+                },
+                '?' => {
+                    regex.push_str("[^/]");
+                    idx += 1;
+                },
+                '[' => {
+                    let end = match chars[idx + 1..].iter().position(|v| *v == ']') {
+                        Some(v) => idx + 1 + v,
+                        None => return Err(String::from("unterminated character class"))
+                    };
+                    let class: String = chars[idx + 1..end].iter().collect();
+                    // a negated class stays within a path component as well
+                    let class = match class.strip_prefix('!') {
+                        Some(v) => format!("^/{}", v),
+                        None => class
+                    };
+                    regex.push('[');
+                    regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                    idx = end + 1;
+                },
+                c => {
+                    regex.push_str(&regex::escape(&c.to_string()));
+                    idx += 1;
+                }
+            }
+        }
+        regex.push('$');
+        Ok(regex)
+    }
+
+    fn parse(source: &str, negated: bool) -> std::result::Result<Glob, String> {
+        let dir_only = source.ends_with('/');
+        let pattern = source.trim_end_matches('/');
+        if pattern.is_empty() {
+            return Err(format!("\"{}\" is not a valid glob, it matches nothing.", source));
+        }
+        let regex = Glob::glob_regex(pattern)
+            .and_then(|v| Regex::new(&v).map_err(|e| e.to_string()))
+            .map_err(|e| format!("\"{}\" is not a valid glob: {}", source, e))?;
+        Ok(Glob { source: String::from(source), regex, dir_only, negated })
+    }
+
+    /// Whether the glob matches an escaped path with `/` separators.
+    pub fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.regex.is_match(relative)
+    }
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        Glob::parse(s, false)
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Glob) -> bool {
+        self.source == other.source && self.negated == other.negated
+    }
+}
+
+/// What the walk does with symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkMode {
+    /// Walk linked directories and take linked files, skipping directories visited before.
+    #[default]
+    Follow,
+    /// Leave links out.
+    Skip
+}
+
+impl FromStr for SymlinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+        match s {
+            "follow" => Ok(SymlinkMode::Follow),
+            "skip" => Ok(SymlinkMode::Skip),
+            _ => Err(format!("\"{}\" is not a valid symlink mode, expected follow or skip.", s))
+        }
+    }
+}
+
+/// Which files of the dictionary the walk visits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraversalOptions {
+    /// Only files matching one of these, or lying in a directory matching one, are taken when there are any.
+    pub include: Vec<Glob>,
+    /// Files and directories left out, along with everything below them.
+    pub exclude: Vec<Glob>,
+    /// Directory levels walked below the dictionary, 0 taking only the files directly in it.
+    pub max_depth: Option<usize>,
+    pub symlinks: SymlinkMode
+}
+
+/// Patterns of one ignore file, relative to the directory holding it.
+#[derive(Debug)]
+struct IgnoreFile {
+    /// Escaped directory of the file relative to the dictionary, empty at the top.
+    base: String,
+    globs: Vec<Glob>
+}
+
+impl IgnoreFile {
+    /// Reads the patterns of an ignore file, one per line. Empty lines and lines starting with `#` are skipped,
+    /// `!` negates a pattern and `\` escapes a leading `#` or `!`.
+    fn load(path: &Path, base: String) -> Result<IgnoreFile> {
+        let content = DictEntry::read_transcript(path)?;
+        let mut globs = Vec::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, negated) = match line.strip_prefix('!') {
+                Some(v) => (v, true),
+                None => (line.strip_prefix('\\').unwrap_or(line), false)
+            };
+            let glob = Glob::parse(pattern, negated)
+                .map_err(|e| Error::rule_file(path, format!("line {}: {}", idx + 1, e)))?;
+            globs.push(glob);
+        }
+        Ok(IgnoreFile { base, globs })
+    }
+}
+
+/// Whether the ignore files from the dictionary down leave a path out, the last matching pattern deciding.
+fn is_ignored(relative: &str, is_dir: bool, ignore_files: &[Rc<IgnoreFile>]) -> bool {
+    let mut ignored = false;
+    for file in ignore_files.iter() {
+        let below = if file.base.is_empty() {
+            relative
+        } else {
+            match relative.strip_prefix(file.base.as_str()).and_then(|v| v.strip_prefix('/')) {
+                Some(v) => v,
+                None => continue
+            }
+        };
+        for glob in file.globs.iter().filter(|v| v.matches(below, is_dir)) {
+            ignored = !glob.negated;
+        }
+    }
+    ignored
+}
+
+impl TraversalOptions {
+    fn is_excluded(&self, relative: &str, is_dir: bool) -> bool {
+        self.exclude.iter().any(|v| v.matches(relative, is_dir))
+    }
+
+    fn is_included(&self, relative: &str) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+        let mut dirs = relative.match_indices('/').map(|(idx, _)| &relative[..idx]);
+        self.include.iter().any(|v| v.matches(relative, false)) ||
+            dirs.any(|dir| self.include.iter().any(|v| v.matches(dir, true)))
+    }
+
+    /// Whether an archive member with the given escaped path, as `archive::for_each_member` passes it, is walked.
+    /// Archives have no ignore files.
+    pub fn selects_member(&self, member: &str) -> bool {
+        let depth = member.matches('/').count();
+        let mut dirs = member.match_indices('/').map(|(idx, _)| &member[..idx]);
+        self.max_depth.map(|v| depth <= v).unwrap_or(true) &&
+            !self.is_excluded(member, false) &&
+            !dirs.any(|dir| self.is_excluded(dir, true)) &&
+            self.is_included(member)
+    }
+
+    /// Every file below `dir` the options select, visited breadth first, or `dir` itself if it is a file.
+    pub fn list_files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        if !dir.is_dir() {
+            return Ok(vec![dir.to_path_buf()]);
+        }
+
+        let mut files: Vec<PathBuf> = Vec::new();
+        // directories with their escaped path relative to `dir`, their depth and the ignore files above them
+        let mut paths: VecDeque<(PathBuf, String, usize, Vec<Rc<IgnoreFile>>)> = VecDeque::new();
+        paths.push_back((dir.to_path_buf(), String::new(), 0, Vec::new()));
+        let mut visited: HashSet<PathBuf> = HashSet::new();
+
+        while let Some((p, relative_dir, depth, mut ignore_files)) = paths.pop_front() {
+            if self.symlinks == SymlinkMode::Follow {
+                let canonical = p.canonicalize().map_err(|e| Error::io(&p, e))?;
+                if !visited.insert(canonical) {
+                    warn!("Skipping {:?}, a link to a directory already walked.", p);
+                    continue;
+                }
+            }
+            debug!("Visiting path {:?}.", p);
+
+            let ignore_path = p.join(IGNORE_FILE);
+            if ignore_path.is_file() {
+                ignore_files.push(Rc::new(IgnoreFile::load(&ignore_path, relative_dir.clone())?));
+            }
+
+            let dir_entries = read_dir(&p).map_err(|e| Error::io(&p, e))?;
+            for entry in dir_entries {
+                let entry = entry.map_err(|e| Error::io(&p, e))?;
+                let path = entry.path();
+                let name = entry.file_name();
+                if name == IGNORE_FILE {
+                    continue;
+                }
+
+                let is_link = symlink_metadata(&path).map_err(|e| Error::io(&path, e))?.file_type().is_symlink();
+                if is_link && self.symlinks == SymlinkMode::Skip {
+                    trace!("Skipping link {:?}.", path);
+                    continue;
+                }
+                let is_dir = path.is_dir();
+                let relative = if relative_dir.is_empty() {
+                    path_encoding::escape(&name)
+                } else {
+                    format!("{}/{}", relative_dir, path_encoding::escape(&name))
+                };
+                if self.is_excluded(&relative, is_dir) || is_ignored(&relative, is_dir, &ignore_files) {
+                    trace!("Leaving out {:?}.", path);
+                    continue;
+                }
+
+                if is_dir {
+                    if self.max_depth.map(|v| depth < v).unwrap_or(true) {
+                        trace!("Adding path {:?}.", path);
+                        paths.push_back((path, relative, depth + 1, ignore_files.clone()));
+                    }
+                } else if self.is_included(&relative) {
+                    trace!("Adding file {:?}.", path);
+                    files.push(path);
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(source: &str) -> Glob {
+        Glob::from_str(source).unwrap()
+    }
+
+    fn ignore_file(base: &str, patterns: &[&str]) -> Rc<IgnoreFile> {
+        let globs = patterns.iter()
+            .map(|v| match v.strip_prefix('!') {
+                Some(v) => Glob::parse(v, true).unwrap(),
+                None => Glob::parse(v, false).unwrap()
+            })
+            .collect();
+        Rc::new(IgnoreFile { base: String::from(base), globs })
+    }
+
+    #[test]
+    fn translates_globs() {
+        assert_eq!(Glob::glob_regex("*.wav").unwrap(), "^(?:.*/)?[^/]*\\.wav$");
+        assert_eq!(Glob::glob_regex("/spk?/**/a.wav").unwrap(), "^spk[^/]/(?:.*/)?a\\.wav$");
+        assert_eq!(Glob::glob_regex("a**b").unwrap(), "^(?:.*/)?a.*b$");
+        assert_eq!(Glob::glob_regex("[!ab]").unwrap(), "^(?:.*/)?[^/ab]$");
+        assert!(Glob::glob_regex("[ab").is_err());
+    }
+
+    #[test]
+    fn matches_names_at_any_depth() {
+        let wav = glob("*.wav");
+        assert!(wav.matches("a.wav", false));
+        assert!(wav.matches("spk1/sub/a.wav", false));
+        assert!(!wav.matches("a.wav.txt", false));
+        assert!(!glob("?.wav").matches("spk/ab.wav", false));
+    }
+
+    #[test]
+    fn anchors_paths_with_a_slash() {
+        let spk = glob("spk1/*.wav");
+        assert!(spk.matches("spk1/a.wav", false));
+        assert!(!spk.matches("x/spk1/a.wav", false));
+        assert!(!spk.matches("spk1/sub/a.wav", false));
+        assert!(glob("/a.wav").matches("a.wav", false));
+        assert!(!glob("/a.wav").matches("spk1/a.wav", false));
+    }
+
+    #[test]
+    fn spans_directories_with_double_stars() {
+        let any = glob("**/tmp/*.wav");
+        assert!(any.matches("tmp/a.wav", false));
+        assert!(any.matches("x/y/tmp/a.wav", false));
+        assert!(!any.matches("xtmp/a.wav", false));
+        let inner = glob("a/**/b");
+        assert!(inner.matches("a/b", false));
+        assert!(inner.matches("a/x/y/b", false));
+        assert!(!inner.matches("ab", false));
+    }
+
+    #[test]
+    fn matches_character_classes() {
+        let class = glob("[ab].wav");
+        assert!(class.matches("a.wav", false));
+        assert!(!class.matches("c.wav", false));
+        let negated = glob("[!ab].wav");
+        assert!(negated.matches("c.wav", false));
+        assert!(!negated.matches("a.wav", false));
+        assert!(!negated.matches("/.wav", false));
+    }
+
+    #[test]
+    fn matches_only_directories_with_a_trailing_slash() {
+        let tmp = glob("tmp/");
+        assert!(tmp.matches("tmp", true));
+        assert!(tmp.matches("spk1/tmp", true));
+        assert!(!tmp.matches("tmp", false));
+        assert!(glob("tmp").matches("tmp", false));
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        assert!(Glob::from_str("/").is_err());
+        assert!(Glob::from_str("[a").is_err());
+    }
+
+    #[test]
+    fn negates_earlier_patterns() {
+        let files = vec![ignore_file("", &["*.wav", "!keep*.wav", "keep_not.wav"])];
+        assert!(is_ignored("a.wav", false, &files));
+        assert!(!is_ignored("keep.wav", false, &files));
+        assert!(is_ignored("keep_not.wav", false, &files));
+        assert!(!is_ignored("a.txt", false, &files));
+    }
+
+    #[test]
+    fn lets_nested_ignore_files_decide() {
+        let files = vec![ignore_file("", &["*.wav"]), ignore_file("spk1", &["!keep.wav", "sub/", "/top.txt"])];
+        assert!(!is_ignored("spk1/keep.wav", false, &files));
+        assert!(!is_ignored("spk1/deeper/keep.wav", false, &files));
+        assert!(is_ignored("spk1/other.wav", false, &files));
+        assert!(is_ignored("spk1/sub", true, &files));
+        assert!(is_ignored("spk1/top.txt", false, &files));
+        assert!(!is_ignored("spk1/deeper/top.txt", false, &files));
+        // the nested file only covers its own directory
+        assert!(is_ignored("spk2/keep.wav", false, &files));
+        assert!(is_ignored("spk10/keep.wav", false, &files));
+        assert!(!is_ignored("spk10/top.txt", false, &files));
+
+        // and a later top level pattern cannot win over it, being read first
+        let files = vec![ignore_file("", &["*.wav", "!*.wav"]), ignore_file("spk1", &["*.wav"])];
+        assert!(!is_ignored("a.wav", false, &files));
+        assert!(is_ignored("spk1/a.wav", false, &files));
+    }
+
+    /// A fresh tree with `a.wav`, `spk1/b.wav`, `spk1/sub/c.wav` and `spk2/d.txt`.
+    fn tree(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dict_walker_traversal_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in ["a.wav", "spk1/b.wav", "spk1/sub/c.wav", "spk2/d.txt"].iter() {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"").unwrap();
+        }
+        dir
+    }
+
+    /// The files the options select below `dir`, relative and sorted.
+    fn list(options: &TraversalOptions, dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = options.list_files(dir).unwrap().iter()
+            .map(|v| v.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn lists_every_file() {
+        let dir = tree("all");
+        assert_eq!(list(&TraversalOptions::default(), &dir), vec!["a.wav", "spk1/b.wav", "spk1/sub/c.wav", "spk2/d.txt"]);
+        assert_eq!(TraversalOptions::default().list_files(&dir.join("a.wav")).unwrap(), vec![dir.join("a.wav")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn limits_the_depth() {
+        let dir = tree("depth");
+        let depth = |v| TraversalOptions { max_depth: Some(v), ..TraversalOptions::default() };
+        assert_eq!(list(&depth(0), &dir), vec!["a.wav"]);
+        assert_eq!(list(&depth(1), &dir), vec!["a.wav", "spk1/b.wav", "spk2/d.txt"]);
+        assert_eq!(list(&depth(2), &dir).len(), 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn applies_globs_and_ignore_files() {
+        let dir = tree("globs");
+        let options = TraversalOptions { include: vec![glob("*.wav")], exclude: vec![glob("sub/")], ..TraversalOptions::default() };
+        assert_eq!(list(&options, &dir), vec!["a.wav", "spk1/b.wav"]);
+        let options = TraversalOptions { include: vec![glob("spk2/")], ..TraversalOptions::default() };
+        assert_eq!(list(&options, &dir), vec!["spk2/d.txt"]);
+
+        std::fs::write(dir.join(IGNORE_FILE), "# comment\n\n*.wav\n").unwrap();
+        std::fs::write(dir.join("spk1").join(IGNORE_FILE), "!b.wav\n").unwrap();
+        assert_eq!(list(&TraversalOptions::default(), &dir), vec!["spk1/b.wav", "spk2/d.txt"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_invalid_ignore_files() {
+        let dir = tree("invalid");
+        std::fs::write(dir.join(IGNORE_FILE), "*.wav\n[a\n").unwrap();
+        let error = TraversalOptions::default().list_files(&dir).unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_or_skips_links() {
+        use std::os::unix::fs::symlink;
+        let dir = tree("links");
+        // a loop back to the top, and a second way into spk1
+        symlink(&dir, dir.join("spk2").join("loop")).unwrap();
+        symlink(dir.join("spk1"), dir.join("alias")).unwrap();
+        symlink(dir.join("a.wav"), dir.join("spk2").join("e.wav")).unwrap();
+
+        let follow = list(&TraversalOptions::default(), &dir);
+        assert_eq!(follow.len(), 5, "{:?}", follow);
+        assert!(follow.contains(&String::from("spk2/e.wav")));
+        assert_eq!(follow.iter().filter(|v| v.ends_with("b.wav")).count(), 1);
+
+        let skip = TraversalOptions { symlinks: SymlinkMode::Skip, ..TraversalOptions::default() };
+        assert_eq!(list(&skip, &dir), vec!["a.wav", "spk1/b.wav", "spk1/sub/c.wav", "spk2/d.txt"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn selects_archive_members() {
+        let options = TraversalOptions {
+            include: vec![glob("*.wav"), glob("notes/")],
+            exclude: vec![glob("tmp/"), glob("spk1/x.wav")],
+            max_depth: Some(1),
+            ..TraversalOptions::default()
+        };
+        assert!(options.selects_member("a.wav"));
+        assert!(options.selects_member("spk1/b.wav"));
+        assert!(options.selects_member("notes/a.txt"));
+        assert!(!options.selects_member("a.txt"));
+        assert!(!options.selects_member("spk1/x.wav"));
+        assert!(!options.selects_member("tmp/a.wav"));
+        assert!(!options.selects_member("spk1/tmp/a.wav"));
+        assert!(!options.selects_member("spk1/sub/c.wav"));
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use error::Error;
+use error::Result;
+
+pub const FORMAT_PCM: u16 = 0x0001;
+pub const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const FORMAT_ALAW: u16 = 0x0006;
+pub const FORMAT_MULAW: u16 = 0x0007;
+pub const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Bytes of the `fmt ` chunk read, up to the sub-format tag of `WAVE_FORMAT_EXTENSIBLE`.
+const FMT_READ_SIZE: usize = 40;
+
+/// What the output records about the audio of an entry.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioInfo {
+    pub duration_seconds: f64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub codec: String
+}
+
+/// The `fmt ` chunk and data size of a RIFF/WAVE file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavHeader {
+    /// Format tag, the sub-format for `WAVE_FORMAT_EXTENSIBLE` files.
+    pub format: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// Byte offset of the sample data.
+    pub data_offset: u64,
+    /// Length of the sample data in bytes.
+    pub data_size: u64
+}
+
+fn u16_at(bytes: &[u8], idx: usize) -> u16 {
+    u16::from(bytes[idx]) | u16::from(bytes[idx + 1]) << 8
+}
+
+fn u32_at(bytes: &[u8], idx: usize) -> u32 {
+    u32::from(u16_at(bytes, idx)) | u32::from(u16_at(bytes, idx + 2)) << 16
+}
+
+impl WavHeader {
+    pub fn read(path: &Path) -> Result<WavHeader> {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let length = file.metadata().map_err(|e| Error::io(path, e))?.len();
+        WavHeader::parse(BufReader::new(file), length)
+            .map_err(|message| Error::AudioHeader { path: path.to_path_buf(), message })
+    }
+
+    /// Walks the chunks up to `data`, skipping everything but `fmt `. `length` is the size of the whole file,
+    /// used for streamed files whose data chunk size was never filled in.
+    pub fn parse<R: Read + Seek>(mut reader: R, length: u64) -> std::result::Result<WavHeader, String> {
+        let mut riff = [0u8; 12];
+        reader.read_exact(&mut riff).map_err(|_| "file is shorter than a RIFF header".to_string())?;
+        if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+            return Err("not a RIFF/WAVE file".to_string());
+        }
+
+        let mut offset: u64 = 12;
+        let mut header: Option<WavHeader> = None;
+        loop {
+            let mut chunk = [0u8; 8];
+            reader.read_exact(&mut chunk).map_err(|_| "no data chunk".to_string())?;
+            let size = u64::from(u32_at(&chunk, 4));
+            offset += 8;
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if size < 16 {
+                        return Err(format!("fmt chunk of {} bytes is too short", size));
+                    }
+                    let available = length.saturating_sub(offset);
+                    if size > available {
+                        return Err(format!("fmt chunk of {} bytes is longer than the {} bytes left in the file", size, available));
+                    }
+                    // the extensible format ends its fields within 40 bytes, the rest is skipped unread
+                    let mut fmt = [0u8; FMT_READ_SIZE];
+                    let read = size.min(FMT_READ_SIZE as u64);
+                    reader.read_exact(&mut fmt[..read as usize]).map_err(|_| "truncated fmt chunk".to_string())?;
+                    reader.seek(SeekFrom::Current((size - read) as i64)).map_err(|e| e.to_string())?;
+                    let mut format = u16_at(&fmt, 0);
+                    if format == FORMAT_EXTENSIBLE && size >= 26 {
+                        // the first two bytes of the sub-format GUID are the actual format tag
+                        format = u16_at(&fmt, 24);
+                    }
+                    header = Some(WavHeader {
+                        format,
+                        channels: u16_at(&fmt, 2),
+                        sample_rate: u32_at(&fmt, 4),
+                        block_align: u16_at(&fmt, 12),
+                        bits_per_sample: u16_at(&fmt, 14),
+                        data_offset: 0,
+                        data_size: 0
+                    });
+                    if size % 2 == 1 {
+                        reader.seek(SeekFrom::Current(1)).map_err(|e| e.to_string())?;
+                    }
+                },
+                b"data" => {
+                    let mut header = header.ok_or_else(|| "data chunk before fmt chunk".to_string())?;
+                    if header.channels == 0 || header.sample_rate == 0 || header.block_align == 0 || header.bits_per_sample == 0 {
+                        return Err(format!("invalid fmt chunk: {} channels, {} Hz, block align {}, {} bits per sample",
+                            header.channels, header.sample_rate, header.block_align, header.bits_per_sample));
+                    }
+                    let available = length.saturating_sub(offset);
+                    if size != u64::from(u32::MAX) && size > available {
+                        return Err(format!("data chunk of {} bytes is truncated to {} bytes", size, available));
+                    }
+                    header.data_offset = offset;
+                    header.data_size = if size == u64::from(u32::MAX) { available } else { size };
+                    return Ok(header);
+                },
+                _ => {
+                    reader.seek(SeekFrom::Current((size + size % 2) as i64)).map_err(|e| e.to_string())?;
+                }
+            }
+            offset += size + size % 2;
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.data_size / u64::from(self.block_align)
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        self.frames() as f64 / f64::from(self.sample_rate)
+    }
+
+    /// Short name of the sample encoding, the hex format tag for anything uncommon.
+    pub fn codec(&self) -> String {
+        match self.format {
+            FORMAT_PCM => String::from("pcm"),
+            FORMAT_IEEE_FLOAT => String::from("float"),
+            FORMAT_ALAW => String::from("alaw"),
+            FORMAT_MULAW => String::from("mulaw"),
+            v => format!("0x{:04x}", v)
+        }
+    }
+
+    pub fn info(&self) -> AudioInfo {
+        AudioInfo {
+            duration_seconds: self.duration_seconds(),
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            bits_per_sample: self.bits_per_sample,
+            codec: self.codec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn fmt_chunk(format: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&format.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        fmt
+    }
+
+    fn chunk(id: &[u8], declared: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&declared.to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<WavHeader, String> {
+        WavHeader::parse(Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    #[test]
+    fn parses_pcm() {
+        let fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        let header = parse(&wav(&[chunk(b"fmt ", 16, &fmt), chunk(b"data", 32000, &[0u8; 32000])])).unwrap();
+        assert_eq!(header.codec(), "pcm");
+        assert_eq!(header.data_offset, 44);
+        assert_eq!(header.frames(), 16000);
+        assert_eq!(header.duration_seconds(), 1.0);
+    }
+
+    #[test]
+    fn parses_non_pcm() {
+        let float = fmt_chunk(FORMAT_IEEE_FLOAT, 2, 8000, 32);
+        let header = parse(&wav(&[chunk(b"fmt ", 16, &float), chunk(b"data", 64, &[0u8; 64])])).unwrap();
+        assert_eq!((header.codec().as_str(), header.channels, header.frames()), ("float", 2, 8));
+
+        // extensible: cbSize, valid bits, channel mask and the GUID starting with the actual format tag
+        let mut extensible = fmt_chunk(FORMAT_EXTENSIBLE, 1, 8000, 8);
+        extensible.extend_from_slice(&[22, 0, 8, 0, 4, 0, 0, 0]);
+        extensible.extend_from_slice(&FORMAT_MULAW.to_le_bytes());
+        extensible.extend_from_slice(&[0u8; 14]);
+        let header = parse(&wav(&[chunk(b"fmt ", 40, &extensible), chunk(b"data", 8, &[0u8; 8])])).unwrap();
+        assert_eq!(header.codec(), "mulaw");
+    }
+
+    #[test]
+    fn describes_pcm16_and_float32() {
+        let pcm = parse(&wav(&[chunk(b"fmt ", 16, &fmt_chunk(FORMAT_PCM, 2, 16000, 16)), chunk(b"data", 16000, &[0u8; 16000])])).unwrap();
+        assert_eq!(pcm.info(), AudioInfo { duration_seconds: 0.25, sample_rate: 16000, channels: 2, bits_per_sample: 16, codec: String::from("pcm") });
+        let float = parse(&wav(&[chunk(b"fmt ", 16, &fmt_chunk(FORMAT_IEEE_FLOAT, 1, 8000, 32)), chunk(b"data", 16000, &[0u8; 16000])])).unwrap();
+        assert_eq!(float.info(), AudioInfo { duration_seconds: 0.5, sample_rate: 8000, channels: 1, bits_per_sample: 32, codec: String::from("float") });
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_long_fmt() {
+        let mut fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        fmt.extend_from_slice(&[0u8; 61]);
+        let bytes = wav(&[chunk(b"LIST", 3, &[1, 2, 3, 0]), chunk(b"fmt ", 77, &fmt), vec![0], chunk(b"data", 4, &[0u8; 4])]);
+        let header = parse(&bytes).unwrap();
+        assert_eq!(header.data_offset, bytes.len() as u64 - 4);
+        assert_eq!(header.frames(), 2);
+    }
+
+    #[test]
+    fn rejects_oversized_fmt() {
+        let fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        let error = parse(&wav(&[chunk(b"fmt ", 0xFFFF_FFF0, &fmt), chunk(b"data", 0, &[])])).unwrap_err();
+        assert!(error.contains("longer than"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_short_fmt() {
+        let fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        let error = parse(&wav(&[chunk(b"fmt ", 14, &fmt[..14]), chunk(b"data", 0, &[])])).unwrap_err();
+        assert!(error.contains("too short"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        let complete = wav(&[chunk(b"fmt ", 16, &fmt), chunk(b"data", 100, &[0u8; 100])]);
+        assert!(parse(&complete[..8]).is_err());
+        assert!(parse(&complete[..30]).is_err());
+        assert!(parse(&complete[..40]).unwrap_err().contains("no data chunk"));
+        assert!(parse(&complete[..60]).unwrap_err().contains("truncated"));
+        assert!(parse(b"RIFX\0\0\0\0WAVE").unwrap_err().contains("not a RIFF/WAVE"));
+    }
+
+    #[test]
+    fn takes_the_file_length_for_streamed_data() {
+        let fmt = fmt_chunk(FORMAT_PCM, 1, 16000, 16);
+        let header = parse(&wav(&[chunk(b"fmt ", 16, &fmt), chunk(b"data", u32::MAX, &[0u8; 10])])).unwrap();
+        assert_eq!(header.data_size, 10);
+    }
+}
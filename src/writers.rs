@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use dict_entry::DictEntry;
+use path_encoding;
+use training_entry::TrainingEntry;
+use wav::WavHeader;
+use error::Error;
+use error::Result;
+
+/// Output of a walk: gets every converted entry along with the `DictEntry` it was built from.
+pub trait DatasetWriter {
+    fn add(&mut self, source: &DictEntry, entry: &TrainingEntry) -> Result<()>;
+
+    /// Writes whatever is still buffered. Returns the number of written entries.
+    fn finish(&mut self) -> Result<usize>;
+}
+
+/// Options of the writers producing a data directory with per speaker files.
+#[derive(Debug, Clone)]
+pub struct DataDirOptions {
+    /// Which directory below the dictionary root names the speaker, 1 being the top level one.
+    /// Entries lying shallower use their containing directory.
+    pub speaker_level: usize
+}
+
+impl Default for DataDirOptions {
+    fn default() -> DataDirOptions {
+        DataDirOptions { speaker_level: 1 }
+    }
+}
+
+/// Derives speaker and utterance IDs from the location of an entry below the dictionary root.
+#[derive(Debug, Clone)]
+pub struct Speakers {
+    root: PathBuf,
+    level: usize
+}
+
+/// Kaldi style identifiers may not contain whitespace.
+pub fn identifier(str: &str) -> String {
+    str.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+impl Speakers {
+    pub fn new(root: &Path, options: &DataDirOptions) -> Speakers {
+        Speakers { root: root.to_path_buf(), level: options.speaker_level.max(1) }
+    }
+
+    /// Speaker of an entry, the one given by an input manifest if there is one.
+    pub fn speaker(&self, source: &DictEntry) -> String {
+        match source.speaker {
+            Some(ref v) => identifier(v),
+            None => self.speaker_of_dir(&source.containing_dir)
+        }
+    }
+
+    fn speaker_of_dir(&self, containing_dir: &Path) -> String {
+        let components = DictEntry::relative_components(&self.root, containing_dir);
+        let speaker = match components.get(self.level - 1).or_else(|| components.last()) {
+            Some(v) => v.clone(),
+            None => self.root.file_name().map(path_encoding::escape).unwrap_or_default()
+        };
+        identifier(&speaker)
+    }
+
+    /// Speaker and utterance ID of an entry. Utterance IDs are the speaker followed by the rest of the entry ID
+    /// below the speaker directory, or the whole entry ID for speakers given by a manifest, so sorting by utterance keeps the speakers together as Kaldi and ESPnet expect.
+    pub fn utterance(&self, source: &DictEntry) -> (String, String) {
+        let speaker = self.speaker(source);
+        let components: Vec<&str> = source.id.split('/').collect();
+        let below = if source.speaker.is_some() {
+            &components[..]
+        } else if components.len() > self.level {
+            &components[self.level..]
+        } else {
+            &components[components.len() - 1..]
+        };
+        let id = format!("{}-{}", speaker, identifier(&below.join("-")));
+        (speaker, id)
+    }
+}
+
+/// Creates `dir/name` and writes one line per item.
+pub fn write_lines<I>(dir: &Path, name: &str, lines: I) -> Result<()> where I: IntoIterator<Item = String> {
+    let path = dir.join(name);
+    let file = File::create(&path).map_err(|e| Error::io(&path, e))?;
+    let mut output = BufWriter::new(file);
+    for line in lines {
+        writeln!(output, "{}", line).map_err(|e| Error::io(&path, e))?;
+    }
+    output.flush().map_err(|e| Error::io(&path, e))
+}
+
+/// Writes `spk2utt` from an utterance ID to speaker map.
+pub fn write_spk2utt<'a, I>(dir: &Path, utt2spk: I) -> Result<()> where I: IntoIterator<Item = (&'a str, &'a str)> {
+    let mut speakers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (id, speaker) in utt2spk {
+        speakers.entry(speaker).or_default().push(id);
+    }
+    write_lines(dir, "spk2utt", speakers.iter()
+        .map(|(speaker, ids)| format!("{} {}", speaker, ids.join(" "))))
+}
+
+/// Duration of the audio of an entry in seconds, from its WAV header unless the entry was probed already.
+pub fn audio_duration(source: &DictEntry, entry: &TrainingEntry) -> Result<f64> {
+    match entry.audio {
+        Some(ref info) => Ok(info.duration_seconds),
+        None => Ok(WavHeader::read(&source.audio_path)?.duration_seconds())
+    }
+}
+
+/// One pretty printed JSON array of `TrainingEntry`s, written by `finish`.
+pub struct JsonWriter {
+    path: PathBuf,
+    entries: Vec<TrainingEntry>
+}
+
+impl JsonWriter {
+    pub fn new(path: &Path) -> JsonWriter {
+        JsonWriter { path: path.to_path_buf(), entries: Vec::new() }
+    }
+}
+
+impl DatasetWriter for JsonWriter {
+    fn add(&mut self, _source: &DictEntry, entry: &TrainingEntry) -> Result<()> {
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<usize> {
+        let json = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| Error::Serialization { path: self.path.clone(), source: e })?;
+        let mut output_file = File::create(&self.path).map_err(|e| Error::io(&self.path, e))?;
+        output_file.write_all(json.as_bytes()).map_err(|e| Error::io(&self.path, e))?;
+        Ok(self.entries.len())
+    }
+}
+
+/// Writes any serializable record per entry as a line of JSON, flushing after every line.
+struct LineWriter {
+    path: PathBuf,
+    output: BufWriter<File>,
+    count: usize
+}
+
+impl LineWriter {
+    fn create(path: &Path) -> Result<LineWriter> {
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        Ok(LineWriter { path: path.to_path_buf(), output: BufWriter::new(file), count: 0 })
+    }
+
+    fn write<T: serde::Serialize>(&mut self, record: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.output, record)
+            .map_err(|e| Error::Serialization { path: self.path.clone(), source: e })?;
+        self.output.write_all(b"\n").map_err(|e| Error::io(&self.path, e))?;
+        self.output.flush().map_err(|e| Error::io(&self.path, e))?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// One `TrainingEntry` per line, written as soon as it is added.
+pub struct JsonlWriter {
+    lines: LineWriter
+}
+
+impl JsonlWriter {
+    pub fn create(path: &Path) -> Result<JsonlWriter> {
+        Ok(JsonlWriter { lines: LineWriter::create(path)? })
+    }
+}
+
+impl DatasetWriter for JsonlWriter {
+    fn add(&mut self, _source: &DictEntry, entry: &TrainingEntry) -> Result<()> {
+        self.lines.write(entry)
+    }
+
+    fn finish(&mut self) -> Result<usize> {
+        Ok(self.lines.count)
+    }
+}
+
+#[derive(Serialize)]
+struct NemoRecord<'a> {
+    audio_filepath: &'a str,
+    duration: f64,
+    text: &'a str
+}
+
+/// NeMo manifest: JSON lines with `audio_filepath`, `duration` and `text`.
+pub struct NemoWriter {
+    lines: LineWriter
+}
+
+impl NemoWriter {
+    pub fn create(path: &Path) -> Result<NemoWriter> {
+        Ok(NemoWriter { lines: LineWriter::create(path)? })
+    }
+}
+
+impl DatasetWriter for NemoWriter {
+    fn add(&mut self, source: &DictEntry, entry: &TrainingEntry) -> Result<()> {
+        self.lines.write(&NemoRecord {
+            audio_filepath: &entry.audio_path,
+            duration: audio_duration(source, entry)?,
+            text: &entry.transcript
+        })
+    }
+
+    fn finish(&mut self) -> Result<usize> {
+        Ok(self.lines.count)
+    }
+}
+
+struct EspnetUtterance {
+    speaker: String,
+    audio_path: String,
+    text: String,
+    duration: f64
+}
+
+/// ESPnet `data/` directory: `wav.scp`, `text`, `utt2spk`, `spk2utt` and `utt2dur`.
+pub struct EspnetWriter {
+    dir: PathBuf,
+    speakers: Speakers,
+    utterances: BTreeMap<String, EspnetUtterance>
+}
+
+impl EspnetWriter {
+    /// `root` is the walked dictionary, the speaker directory level is counted from it.
+    pub fn new(dir: &Path, root: &Path, options: &DataDirOptions) -> EspnetWriter {
+        EspnetWriter { dir: dir.to_path_buf(), speakers: Speakers::new(root, options), utterances: BTreeMap::new() }
+    }
+}
+
+impl DatasetWriter for EspnetWriter {
+    fn add(&mut self, source: &DictEntry, entry: &TrainingEntry) -> Result<()> {
+        let (speaker, id) = self.speakers.utterance(source);
+        if let Some(other) = self.utterances.get(&id) {
+            return Err(Error::NamingCollision {
+                first: PathBuf::from(&other.audio_path),
+                second: source.audio_path.clone()
+            });
+        }
+        let utterance = EspnetUtterance {
+            speaker,
+            audio_path: entry.audio_path.clone(),
+            text: entry.transcript.clone(),
+            duration: audio_duration(source, entry)?
+        };
+        self.utterances.insert(id, utterance);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<usize> {
+        create_dir_all(&self.dir).map_err(|e| Error::io(&self.dir, e))?;
+        write_lines(&self.dir, "wav.scp", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.audio_path)))?;
+        write_lines(&self.dir, "text", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.text)))?;
+        write_lines(&self.dir, "utt2spk", self.utterances.iter()
+            .map(|(id, v)| format!("{} {}", id, v.speaker)))?;
+        write_lines(&self.dir, "utt2dur", self.utterances.iter()
+            .map(|(id, v)| format!("{} {:.3}", id, v.duration)))?;
+        write_spk2utt(&self.dir, self.utterances.iter().map(|(id, v)| (id.as_str(), v.speaker.as_str())))?;
+        Ok(self.utterances.len())
+    }
+}